@@ -0,0 +1,248 @@
+// Shared download cache, files are stored by their sha512 so every pack can reuse them.
+
+use crate::config::CacheConfig;
+use crate::download::sha512_file;
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+pub struct Cache {
+    root: PathBuf,
+}
+
+impl Cache {
+    /// Opens the cache from the config, `None` when caching is disabled.
+    pub fn open(config: &CacheConfig) -> Result<Option<Cache>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+
+        let root = match &config.dir {
+            Some(dir) => dir.clone(),
+            None => dirs::cache_dir()
+                .ok_or_else(|| anyhow!("Could not find a cache directory for this platform"))?
+                .join("modrinth-collection-downloader"),
+        };
+
+        Ok(Some(Cache { root }))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where the entry for `sha512` lives, `None` unless it is a sha512 in hex. Hashes can come
+    /// from a downloaded modpack, anything else could point outside the cache.
+    fn entry_path(&self, sha512: &str) -> Option<PathBuf> {
+        if sha512.len() != 128 || !sha512.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let sha512 = sha512.to_ascii_lowercase();
+        Some(self.root.join("files").join(&sha512[..2]).join(sha512))
+    }
+
+    /// Whether a file with this sha512 is cached.
+    pub async fn contains(&self, sha512: &str) -> Result<bool> {
+        match self.entry_path(sha512) {
+            Some(entry) => Ok(fs::try_exists(entry).await?),
+            None => Ok(false),
+        }
+    }
+
+    /// Places the cached file for `sha512` at `dest`, returns false if it isn't cached. Entries
+    /// are hardlinked into packs, so a jar edited in one pack changes the entry too. Those no
+    /// longer match their hash and are evicted instead of being handed out.
+    pub async fn link_into(&self, sha512: &str, dest: &Path) -> Result<bool> {
+        let Some(entry) = self.entry_path(sha512) else {
+            return Ok(false);
+        };
+        if !fs::try_exists(&entry).await? {
+            return Ok(false);
+        }
+
+        if !sha512_file(&entry).await?.eq_ignore_ascii_case(sha512) {
+            fs::remove_file(&entry).await?;
+            return Ok(false);
+        }
+        if fs::try_exists(dest).await? {
+            fs::remove_file(dest).await?;
+        }
+        link_or_copy(&entry, dest).await?;
+
+        Ok(true)
+    }
+
+    /// Adds a verified file to the cache.
+    pub async fn store(&self, sha512: &str, src: &Path) -> Result<()> {
+        let Some(entry) = self.entry_path(sha512) else {
+            return Ok(());
+        };
+        if fs::try_exists(&entry).await? {
+            return Ok(());
+        }
+
+        let parent = entry.parent().unwrap();
+        fs::create_dir_all(parent).await?;
+
+        // Write next to the entry first, so a crash never leaves a truncated entry behind
+        let tmp = entry.with_extension("tmp");
+        link_or_copy(src, &tmp).await?;
+        fs::rename(&tmp, &entry).await?;
+
+        Ok(())
+    }
+
+    /// Remembers a directory that takes files from the cache, so `gc` knows what is in use.
+    pub async fn register_pack(&self, dir: &Path) -> Result<()> {
+        let dir = fs::canonicalize(dir).await?;
+        let mut packs = self.packs().await?;
+
+        if !packs.contains(&dir) {
+            packs.push(dir);
+            fs::create_dir_all(&self.root).await?;
+            fs::write(
+                self.root.join("packs.json"),
+                serde_json::to_string_pretty(&packs)?,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn packs(&self) -> Result<Vec<PathBuf>> {
+        let path = self.root.join("packs.json");
+        if !fs::try_exists(&path).await? {
+            return Ok(Vec::new());
+        }
+
+        let contents = fs::read_to_string(&path).await?;
+        serde_json::from_str(&contents).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Number of cached files and their total size in bytes.
+    pub async fn size(&self) -> Result<(usize, u64)> {
+        let mut count = 0;
+        let mut bytes = 0;
+
+        for (_, path) in self.entries().await? {
+            count += 1;
+            bytes += fs::metadata(&path).await?.len();
+        }
+
+        Ok((count, bytes))
+    }
+
+    /// Evicts every entry that isn't used by a registered pack directory anymore. Packs that no
+    /// longer exist are forgotten. Returns the number of evicted files and the bytes freed. A dry
+    /// run only counts them.
+    pub async fn gc(&self, dry_run: bool) -> Result<(usize, u64)> {
+        let mut packs = Vec::new();
+        let mut referenced = HashSet::new();
+
+        for pack in self.packs().await? {
+            if !fs::try_exists(&pack).await? {
+                continue;
+            }
+
+            let mut entries = fs::read_dir(&pack).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_file() {
+                    referenced.insert(sha512_file(&entry.path()).await?);
+                }
+            }
+            packs.push(pack);
+        }
+
+        let mut evicted = 0;
+        let mut freed = 0;
+        for (sha512, path) in self.entries().await? {
+            if !referenced.contains(&sha512) {
+                freed += fs::metadata(&path).await?.len();
+                if !dry_run {
+                    fs::remove_file(&path).await?;
+                }
+                evicted += 1;
+            }
+        }
+
+        if !dry_run && fs::try_exists(&self.root).await? {
+            fs::write(
+                self.root.join("packs.json"),
+                serde_json::to_string_pretty(&packs)?,
+            )
+            .await?;
+        }
+
+        Ok((evicted, freed))
+    }
+
+    /// All cached files as (sha512, path) pairs.
+    async fn entries(&self) -> Result<Vec<(String, PathBuf)>> {
+        let files = self.root.join("files");
+        let mut found = Vec::new();
+
+        if !fs::try_exists(&files).await? {
+            return Ok(found);
+        }
+
+        let mut buckets = fs::read_dir(&files).await?;
+        while let Some(bucket) = buckets.next_entry().await? {
+            if !bucket.file_type().await?.is_dir() {
+                continue;
+            }
+
+            let mut entries = fs::read_dir(bucket.path()).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.file_type().await?.is_file() && !name.ends_with(".tmp") {
+                    found.push((name, entry.path()));
+                }
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+/// Hardlinks `src` to `dest`, falling back to a copy when linking isn't possible, for example
+/// across drives.
+async fn link_or_copy(src: &Path, dest: &Path) -> Result<()> {
+    if fs::hard_link(src, dest).await.is_err() {
+        fs::copy(src, dest).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_accepts_sha512_keys() {
+        let cache = Cache {
+            root: PathBuf::from("cache"),
+        };
+
+        let sha512 = "Ab".repeat(64);
+        assert_eq!(
+            cache.entry_path(&sha512),
+            Some(Path::new("cache/files/ab").join("ab".repeat(64)))
+        );
+
+        for key in [
+            String::new(),
+            "/home/user/.bashrc".to_string(),
+            format!("/{}", "a".repeat(127)),
+            format!("../../..{}", "a".repeat(120)),
+            format!("aé{}", "a".repeat(125)),
+            "a".repeat(127),
+            "g".repeat(128),
+        ] {
+            assert_eq!(cache.entry_path(&key), None, "{key} was accepted");
+        }
+    }
+}
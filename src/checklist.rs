@@ -0,0 +1,139 @@
+// Mod checklist, lets the user pick which of the resolved projects get downloaded.
+
+use crate::config::{Config, save_exclude};
+use crate::modrinth::{ApiClient, ModrinthProject, Project, get_projects};
+use anyhow::Result;
+use colored::Colorize;
+use indicatif::HumanBytes;
+use inquire::MultiSelect;
+use std::collections::HashMap;
+
+/// Shows every project with its title, version, size and side support. Projects on the exclude
+/// list start out unchecked. Changes to the selection are written back to the exclude list, so the
+/// next run remembers them, unless `save` is false.
+pub async fn select_projects(
+    api: &ApiClient,
+    links: Vec<ModrinthProject>,
+    config: &Config,
+    save: bool,
+) -> Result<Vec<ModrinthProject>> {
+    if links.is_empty() {
+        return Ok(links);
+    }
+
+    let projects = lookup(api, &links).await;
+    let matches = |entry: &str, link: &ModrinthProject| matches(&projects, entry, link);
+
+    let labels: Vec<String> = links
+        .iter()
+        .map(|link| label(link, projects.get(&link.id)))
+        .collect();
+    let defaults: Vec<usize> = links
+        .iter()
+        .enumerate()
+        .filter(|(_, link)| !config.exclude.iter().any(|e| matches(e, link)))
+        .map(|(i, _)| i)
+        .collect();
+
+    let selected: Vec<usize> = MultiSelect::new(
+        "Which mods would you like to download? Unchecked mods are remembered for next time.",
+        labels,
+    )
+    .with_default(&defaults)
+    .with_page_size(15)
+    .raw_prompt()
+    .map_err(|e| anyhow::anyhow!("Mod selection failed: {e}"))?
+    .into_iter()
+    .map(|option| option.index)
+    .collect();
+
+    // Keep exclusions for projects that weren't part of this run
+    let mut exclude: Vec<String> = config
+        .exclude
+        .iter()
+        .filter(|e| !links.iter().any(|link| matches(e, link)))
+        .cloned()
+        .collect();
+
+    let mut chosen = Vec::new();
+    for (i, link) in links.into_iter().enumerate() {
+        if selected.contains(&i) {
+            chosen.push(link);
+        } else {
+            exclude.push(
+                projects
+                    .get(&link.id)
+                    .map(|p| p.slug.clone())
+                    .unwrap_or(link.id),
+            );
+        }
+    }
+
+    if save && exclude != config.exclude {
+        save_exclude(&exclude).await?;
+        say!(
+            "{}",
+            format!("Saved {} excluded mods to config.json", exclude.len()).bright_green()
+        );
+    }
+
+    Ok(chosen)
+}
+
+/// Drops the projects on the exclude list without asking, for runs that can't prompt.
+pub async fn apply_exclude(
+    api: &ApiClient,
+    links: Vec<ModrinthProject>,
+    config: &Config,
+) -> Result<Vec<ModrinthProject>> {
+    if links.is_empty() || config.exclude.is_empty() {
+        return Ok(links);
+    }
+
+    let projects = lookup(api, &links).await;
+    let (excluded, chosen): (Vec<ModrinthProject>, Vec<ModrinthProject>) = links
+        .into_iter()
+        .partition(|link| config.exclude.iter().any(|e| matches(&projects, e, link)));
+
+    if !excluded.is_empty() {
+        say!("Skipping {} excluded mods", excluded.len());
+    }
+
+    Ok(chosen)
+}
+
+/// Project details by id. Titles and sides are only cosmetic, so a failed lookup (e.g. offline)
+/// just leaves them out.
+async fn lookup(api: &ApiClient, links: &[ModrinthProject]) -> HashMap<String, Project> {
+    let ids: Vec<String> = links.iter().map(|l| l.id.clone()).collect();
+    get_projects(api, &ids)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|p| (p.id.clone(), p))
+        .collect()
+}
+
+/// Exclude entries can be the project id or its slug.
+fn matches(projects: &HashMap<String, Project>, entry: &str, link: &ModrinthProject) -> bool {
+    entry == link.id || projects.get(&link.id).is_some_and(|p| p.slug == entry)
+}
+
+fn label(link: &ModrinthProject, project: Option<&Project>) -> String {
+    let title = project.map(|p| p.title.as_str()).unwrap_or(&link.name);
+    let version = if link.version_number.is_empty() {
+        &link.name
+    } else {
+        &link.version_number
+    };
+
+    let mut label = format!("{title} {version} ({})", HumanBytes(link.size));
+    if let Some(project) = project {
+        label.push_str(&format!(
+            " [client: {}, server: {}]",
+            project.client_side, project.server_side
+        ));
+    }
+
+    label
+}
@@ -0,0 +1,435 @@
+// Compatibility diagnostics, which Minecraft versions and loaders projects actually support.
+
+use crate::modrinth::{ApiClient, ProjectVersion, get_project, get_project_versions};
+use anyhow::Result;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// How many supported Minecraft versions are listed before the rest is summarized.
+const SHOWN_VERSIONS: usize = 8;
+
+/// Why a project has no version for the chosen loader and Minecraft version, and what would work
+/// instead.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FailureReport {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    /// Every loader the project has a build for.
+    pub loaders: Vec<String>,
+    /// Every Minecraft version the project has a build for, newest first.
+    pub game_versions: Vec<String>,
+    /// Closest Minecraft versions that have a build for the chosen loader.
+    pub nearest_older: Option<String>,
+    pub nearest_newer: Option<String>,
+    /// A loader the chosen one can also run, which has a build for the chosen version.
+    pub loader_fallback: Option<String>,
+    pub verdict: String,
+}
+
+/// Looks at the full version list of a project to explain why nothing matched.
+pub async fn explain_failure(
+    api: &ApiClient,
+    id: &str,
+    loader: &str,
+    version: &str,
+) -> Result<FailureReport> {
+    let project = get_project(api, id).await?;
+    let versions = get_project_versions(api, id).await?;
+
+    let mut loaders: Vec<String> = versions.iter().flat_map(|v| v.loaders.clone()).collect();
+    loaders.sort();
+    loaders.dedup();
+
+    let game_versions = sort_game_versions(versions.iter().flat_map(|v| v.game_versions.clone()));
+    let loader_builds = game_versions_for(&versions, loader);
+    let (nearest_older, nearest_newer) = nearest(&loader_builds, version);
+
+    let loader_fallback = fallback_loaders(loader, version)
+        .iter()
+        .find(|fallback| {
+            game_versions_for(&versions, fallback)
+                .iter()
+                .any(|v| v == version)
+        })
+        .map(|fallback| fallback.to_string());
+
+    let verdict = if let Some(fallback) = &loader_fallback {
+        format!("Has a {fallback} build for {version}, which {loader} can load")
+    } else if loader_builds.iter().any(|v| v == version) {
+        format!("Has a {loader} build for {version}, but it has no downloadable file")
+    } else if loader_builds.is_empty() {
+        format!("Doesn't support {loader} at all, drop it or look for an alternative")
+    } else if let Some(older) = &nearest_older {
+        format!("Not updated to {version} yet, works on Minecraft {older}")
+    } else if let Some(newer) = &nearest_newer {
+        format!("Only supports newer Minecraft, the oldest {loader} build is for {newer}")
+    } else {
+        format!("No {loader} build for {version}, wait for an update")
+    };
+
+    Ok(FailureReport {
+        id: project.id,
+        title: project.title,
+        url: format!(
+            "https://modrinth.com/{}/{}",
+            project.project_type, project.slug
+        ),
+        loaders,
+        game_versions,
+        nearest_older,
+        nearest_newer,
+        loader_fallback,
+        verdict,
+    })
+}
+
+impl FailureReport {
+    pub fn print(&self, loader: &str) {
+        say!("{}, {}", self.title.bright_yellow(), self.url);
+        say!("  Loaders: {}", self.loaders.join(", "));
+
+        let mut shown = self
+            .game_versions
+            .iter()
+            .take(SHOWN_VERSIONS)
+            .cloned()
+            .collect::<Vec<String>>()
+            .join(", ");
+        if self.game_versions.len() > SHOWN_VERSIONS {
+            shown.push_str(&format!(
+                " (+{} older)",
+                self.game_versions.len() - SHOWN_VERSIONS
+            ));
+        }
+        say!("  Minecraft: {shown}");
+
+        if self.nearest_older.is_some() || self.nearest_newer.is_some() {
+            say!(
+                "  Nearest {loader} builds: {} older, {} newer",
+                self.nearest_older.as_deref().unwrap_or("none"),
+                self.nearest_newer.as_deref().unwrap_or("none")
+            );
+        }
+
+        say!("  {}", self.verdict.bright_green());
+    }
+}
+
+/// Loaders the downloader supports, in the order they are shown.
+pub const LOADERS: &[&str] = &["fabric", "neoforge", "quilt", "forge"];
+
+/// How many projects support each Minecraft release, per loader.
+#[derive(Serialize)]
+pub struct Matrix {
+    /// Number of projects that were checked.
+    pub total: usize,
+    pub loaders: Vec<String>,
+    /// Minecraft releases, newest first, with a count per loader in the order of `loaders`.
+    pub rows: Vec<MatrixRow>,
+}
+
+#[derive(Serialize)]
+pub struct MatrixRow {
+    pub game_version: String,
+    pub counts: Vec<usize>,
+}
+
+/// The version to pick for a loader.
+#[derive(Serialize)]
+pub struct Recommendation {
+    pub loader: String,
+    pub game_version: String,
+    pub supported: usize,
+    pub total: usize,
+}
+
+/// Fetches the full version list of every project and counts, for each Minecraft release and
+/// loader, how many of them have a build. Builds a loader can fall back to are counted as well,
+/// see [`fallback_loaders`].
+pub async fn compatibility_matrix(
+    api: &ApiClient,
+    ids: &[String],
+    loaders: &[&str],
+) -> Result<Matrix> {
+    let mut counts: HashMap<(String, String), usize> = HashMap::new();
+    let mut game_versions = HashSet::new();
+
+    for id in ids {
+        let versions = get_project_versions(api, id).await?;
+
+        let supported: HashSet<(&str, &str)> = versions
+            .iter()
+            .flat_map(|v| {
+                v.loaders.iter().flat_map(move |l| {
+                    v.game_versions
+                        .iter()
+                        .map(move |g| (l.as_str(), g.as_str()))
+                })
+            })
+            .collect();
+
+        for &(_, game_version) in &supported {
+            if release_key(game_version).is_some() {
+                game_versions.insert(game_version.to_string());
+            }
+        }
+
+        for loader in loaders {
+            let mut loader_versions: HashSet<&str> = supported
+                .iter()
+                .filter(|(l, g)| l == loader || fallback_loaders(loader, g).contains(l))
+                .map(|(_, g)| *g)
+                .collect();
+            loader_versions.retain(|g| release_key(g).is_some());
+
+            for game_version in loader_versions {
+                *counts
+                    .entry((loader.to_string(), game_version.to_string()))
+                    .or_default() += 1;
+            }
+        }
+    }
+
+    let rows = sort_game_versions(game_versions)
+        .into_iter()
+        .map(|game_version| MatrixRow {
+            counts: loaders
+                .iter()
+                .map(|l| {
+                    counts
+                        .get(&(l.to_string(), game_version.clone()))
+                        .copied()
+                        .unwrap_or(0)
+                })
+                .collect(),
+            game_version,
+        })
+        .collect();
+
+    Ok(Matrix {
+        total: ids.len(),
+        loaders: loaders.iter().map(|l| l.to_string()).collect(),
+        rows,
+    })
+}
+
+impl Matrix {
+    /// The newest release with the highest coverage for each loader. Rows are newest first, so the
+    /// first maximum wins ties.
+    pub fn recommendations(&self) -> Vec<Recommendation> {
+        self.loaders
+            .iter()
+            .enumerate()
+            .filter_map(|(i, loader)| {
+                let best = self
+                    .rows
+                    .iter()
+                    .fold(None::<&MatrixRow>, |best, row| match best {
+                        Some(b) if b.counts[i] >= row.counts[i] => Some(b),
+                        _ => Some(row),
+                    })
+                    .filter(|row| row.counts[i] > 0)?;
+
+                Some(Recommendation {
+                    loader: loader.clone(),
+                    game_version: best.game_version.clone(),
+                    supported: best.counts[i],
+                    total: self.total,
+                })
+            })
+            .collect()
+    }
+
+    /// Prints the newest `limit` releases that at least one project supports.
+    pub fn print(&self, limit: usize) {
+        let width = self
+            .rows
+            .iter()
+            .map(|r| r.game_version.len())
+            .max()
+            .unwrap_or(0)
+            .max("Minecraft".len());
+        let columns = self
+            .loaders
+            .iter()
+            .map(|l| l.len())
+            .max()
+            .unwrap_or(0)
+            .max(format!("{0}/{0}", self.total).len());
+
+        let mut header = format!("{:<width$}", "Minecraft");
+        for loader in &self.loaders {
+            header.push_str(&format!("  {loader:>columns$}"));
+        }
+        say!("{header}");
+
+        for row in self.rows.iter().take(limit) {
+            let mut line = format!("{:<width$}", row.game_version);
+            for count in &row.counts {
+                let cell = format!("{:>columns$}", format!("{count}/{}", self.total));
+                if *count == self.total {
+                    line.push_str(&format!("  {}", cell.bright_green()));
+                } else {
+                    line.push_str(&format!("  {cell}"));
+                }
+            }
+            say!("{line}");
+        }
+
+        let recommendations = self.recommendations();
+        if recommendations.is_empty() {
+            say!(
+                "{}",
+                "No project supports any of these loaders".bright_red()
+            );
+        }
+        for r in recommendations {
+            let coverage = if r.supported == r.total {
+                "full coverage".to_string()
+            } else {
+                format!("highest coverage, {} missing", r.total - r.supported)
+            };
+            say!(
+                "{}",
+                format!(
+                    "Recommended for {}: Minecraft {} ({}/{}, {coverage})",
+                    r.loader, r.game_version, r.supported, r.total
+                )
+                .bright_green()
+            );
+        }
+    }
+}
+
+/// Loaders that can also run mods built for `loader`. Quilt loads Fabric mods, and NeoForge
+/// still loaded Forge mods on 1.20.1.
+pub fn fallback_loaders(loader: &str, minecraft_version: &str) -> &'static [&'static str] {
+    match (loader, minecraft_version) {
+        ("quilt", _) => &["fabric"],
+        ("neoforge", "1.20.1") => &["forge"],
+        _ => &[],
+    }
+}
+
+/// Minecraft versions that have a build for `loader`, newest first.
+fn game_versions_for(versions: &[ProjectVersion], loader: &str) -> Vec<String> {
+    sort_game_versions(
+        versions
+            .iter()
+            .filter(|v| v.loaders.iter().any(|l| l == loader))
+            .flat_map(|v| v.game_versions.clone()),
+    )
+}
+
+/// Dedupes and sorts Minecraft versions, newest release first, snapshots and other versions at
+/// the end.
+pub fn sort_game_versions(versions: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut versions: Vec<String> = versions.into_iter().collect();
+    versions.sort_by(|a, b| compare_game_versions(b, a));
+    versions.dedup();
+    versions
+}
+
+/// Orders release versions numerically, anything that isn't a release sorts below them.
+pub fn compare_game_versions(a: &str, b: &str) -> Ordering {
+    match (release_key(a), release_key(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => a.cmp(b),
+    }
+}
+
+/// `1.21.1` as `[1, 21, 1]`, `None` for snapshots and pre-releases.
+fn release_key(version: &str) -> Option<Vec<u32>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// The closest release below and above `target` among `available`.
+fn nearest(available: &[String], target: &str) -> (Option<String>, Option<String>) {
+    let Some(target) = release_key(target) else {
+        return (None, None);
+    };
+
+    let mut older: Option<(Vec<u32>, &String)> = None;
+    let mut newer: Option<(Vec<u32>, &String)> = None;
+    for version in available {
+        let Some(key) = release_key(version) else {
+            continue;
+        };
+
+        if key < target && older.as_ref().is_none_or(|(k, _)| &key > k) {
+            older = Some((key, version));
+        } else if key > target && newer.as_ref().is_none_or(|(k, _)| &key < k) {
+            newer = Some((key, version));
+        }
+    }
+
+    (older.map(|(_, v)| v.clone()), newer.map(|(_, v)| v.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(versions: &[&str]) -> Vec<String> {
+        versions.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn sorts_releases_numerically() {
+        assert_eq!(
+            sort_game_versions(strings(&[
+                "1.9", "24w31a", "1.21.1", "1.20", "1.21.1", "1.21"
+            ])),
+            strings(&["1.21.1", "1.21", "1.20", "1.9", "24w31a"])
+        );
+    }
+
+    #[test]
+    fn recommends_newest_best_coverage() {
+        let row = |game_version: &str, counts: &[usize]| MatrixRow {
+            game_version: game_version.to_string(),
+            counts: counts.to_vec(),
+        };
+        let matrix = Matrix {
+            total: 3,
+            loaders: strings(&["fabric", "forge", "quilt"]),
+            rows: vec![
+                row("1.21.1", &[2, 0, 0]),
+                row("1.21", &[3, 1, 0]),
+                row("1.20.1", &[3, 2, 0]),
+            ],
+        };
+
+        let recommendations: Vec<(String, String, usize)> = matrix
+            .recommendations()
+            .into_iter()
+            .map(|r| (r.loader, r.game_version, r.supported))
+            .collect();
+        assert_eq!(
+            recommendations,
+            vec![
+                ("fabric".to_string(), "1.21".to_string(), 3),
+                ("forge".to_string(), "1.20.1".to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_nearest_versions() {
+        let available = strings(&["1.20.1", "1.19.2", "1.21.4", "1.21.3", "24w31a"]);
+        assert_eq!(
+            nearest(&available, "1.21.1"),
+            (Some("1.20.1".to_string()), Some("1.21.3".to_string()))
+        );
+        assert_eq!(
+            nearest(&available, "1.22"),
+            (Some("1.21.4".to_string()), None)
+        );
+        assert_eq!(nearest(&available, "24w31a"), (None, None));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::license::LicensePolicy;
+use crate::packwiz::DependencyPolicy;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::fs;
+
+/// Settings read from `config.json` next to the executable. Every field has a default, so the
+/// file is optional and only needs the values you want to change.
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Serve everything from the prefetched store instead of the network.
+    pub offline: bool,
+    pub download: DownloadConfig,
+    pub cache: CacheConfig,
+    pub api: ApiConfig,
+    /// Project ids or slugs deselected in the mod checklist, they start out unchecked next time.
+    pub exclude: Vec<String>,
+    /// What happens to all rights reserved or unlicensed mods in exports that bundle jars.
+    pub license_policy: LicensePolicy,
+    pub packwiz: PackwizConfig,
+}
+
+/// packwiz pack building.
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct PackwizConfig {
+    /// What happens to required dependencies that aren't part of the pack.
+    pub dependencies: DependencyPolicy,
+}
+
+/// Download behaviour.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct DownloadConfig {
+    /// How many times a file is retried after a retryable failure.
+    pub retries: u32,
+    /// Delay before the first retry in milliseconds, doubled on every following attempt.
+    pub backoff_ms: u64,
+    /// How many files are downloaded at the same time.
+    pub concurrency: usize,
+    /// Combined download speed limit in bytes per second, 0 means unlimited.
+    pub bandwidth_limit: u64,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff_ms: 500,
+            concurrency: 8,
+            bandwidth_limit: 0,
+        }
+    }
+}
+
+/// Loads the config file, falling back to defaults when it doesn't exist.
+pub async fn load_config() -> Result<Config> {
+    let path = config_path()?;
+
+    if !fs::try_exists(&path).await? {
+        return Ok(Config::default());
+    }
+
+    let contents = fs::read_to_string(&path).await?;
+    serde_json::from_str(&contents).with_context(|| format!("parsing {}", path.display()))
+}
+
+/// Writes the exclude list back to the config file. Only that key is touched, so the rest of the
+/// file stays the way the user wrote it, and flags like `--offline` never end up in it.
+pub async fn save_exclude(exclude: &[String]) -> Result<()> {
+    let path = config_path()?;
+
+    let mut json = if fs::try_exists(&path).await? {
+        let contents = fs::read_to_string(&path).await?;
+        serde_json::from_str(&contents).with_context(|| format!("parsing {}", path.display()))?
+    } else {
+        serde_json::json!({})
+    };
+
+    let object = json
+        .as_object_mut()
+        .with_context(|| format!("{} is not a JSON object", path.display()))?;
+    object.insert("exclude".to_string(), serde_json::to_value(exclude)?);
+
+    fs::write(&path, serde_json::to_string_pretty(&json)?).await?;
+
+    Ok(())
+}
+
+fn config_path() -> Result<PathBuf> {
+    let mut path = std::env::current_exe()?;
+    path.pop();
+    path.push("config.json");
+
+    Ok(path)
+}
+
+/// Shared download cache.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// Overrides the platform cache directory.
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+        }
+    }
+}
+
+/// Modrinth API client behaviour.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// How long a stored API response is reused before it is revalidated, in seconds.
+    pub cache_ttl_secs: u64,
+    /// Personal access token, see [`resolve_token`] for the other places it can come from.
+    pub token: Option<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            cache_ttl_secs: 600,
+            token: None,
+        }
+    }
+}
+
+/// Environment variable holding a Modrinth personal access token.
+pub const TOKEN_ENV: &str = "MODRINTH_TOKEN";
+
+/// Finds the personal access token to use. The `MODRINTH_TOKEN` environment variable wins over
+/// `api.token` in the config, which wins over the credentials file.
+pub async fn resolve_token(config: &Config) -> Result<Option<String>> {
+    if let Ok(token) = std::env::var(TOKEN_ENV)
+        && !token.trim().is_empty()
+    {
+        return Ok(Some(token.trim().to_string()));
+    }
+
+    if let Some(token) = &config.api.token
+        && !token.trim().is_empty()
+    {
+        return Ok(Some(token.trim().to_string()));
+    }
+
+    if let Some(path) = credentials_path()
+        && fs::try_exists(&path).await?
+    {
+        let token = fs::read_to_string(&path).await?;
+        if !token.trim().is_empty() {
+            return Ok(Some(token.trim().to_string()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Plain text file containing only the token, in the platform config directory
+/// (`~/.config/modrinth-collection-downloader/credentials` on Linux).
+pub fn credentials_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| {
+        dir.join("modrinth-collection-downloader")
+            .join("credentials")
+    })
+}
@@ -1,28 +1,139 @@
+use crate::cache::Cache;
+use crate::config::Config;
 use crate::modrinth::ModrinthProject;
-use anyhow::Result;
+use crate::output;
+use crate::throttle::Throttle;
+use anyhow::{Result, anyhow};
+use colored::Colorize;
 use futures::stream::{FuturesUnordered, StreamExt};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use reqwest::Url;
-use reqwest::header::USER_AGENT;
+use reqwest::header::{RANGE, USER_AGENT};
+use reqwest::{Client, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use std::fmt;
+use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::fs::{self, File};
-use tokio::io::AsyncWriteExt;
+use std::time::Duration;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::sync::Semaphore;
 
-/// Download up to 8 files at a time
+/// How many times a dropped connection is resumed before the download is given up.
+const MAX_RESUMES: usize = 3;
+
+/// What happened to a single project during the download step.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DownloadOutcome {
+    Downloaded {
+        path: PathBuf,
+    },
+    /// The file was already on disk with the expected hash.
+    UpToDate {
+        path: PathBuf,
+    },
+    /// The file was taken from the shared download cache.
+    Cached {
+        path: PathBuf,
+    },
+    Failed {
+        reason: String,
+        attempts: u32,
+    },
+    HashMismatch {
+        expected: String,
+        actual: String,
+    },
+}
+
+impl DownloadOutcome {
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self,
+            DownloadOutcome::Downloaded { .. }
+                | DownloadOutcome::UpToDate { .. }
+                | DownloadOutcome::Cached { .. }
+        )
+    }
+
+    /// Where the file ended up, `None` when it didn't.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            DownloadOutcome::Downloaded { path }
+            | DownloadOutcome::UpToDate { path }
+            | DownloadOutcome::Cached { path } => Some(path),
+            DownloadOutcome::Failed { .. } | DownloadOutcome::HashMismatch { .. } => None,
+        }
+    }
+}
+
+/// What a dry run expects to happen to a file.
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlannedAction {
+    Download,
+    /// Already on disk with the expected hash.
+    Skip,
+    /// Linked from the shared download cache.
+    Cache,
+}
+
+/// A project together with its download outcome.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DownloadResult {
+    #[serde(flatten)]
+    pub project: ModrinthProject,
+    #[serde(flatten)]
+    pub outcome: DownloadOutcome,
+}
+
+/// Raised when a finished download doesn't match the sha512 reported by Modrinth.
+#[derive(Debug)]
+struct HashMismatch {
+    expected: String,
+    actual: String,
+}
+
+impl fmt::Display for HashMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hash mismatch, expected sha512 {} but got {}",
+            self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for HashMismatch {}
+
+/// Download up to `config.concurrency` files at a time, sharing the optional bandwidth limit and
+/// retrying failed files according to the config. Files found in the cache are linked instead of
+/// downloaded, and new downloads are added to it. In offline mode the cache is the only source.
+/// Returns an outcome for every project.
 pub async fn download_files(
     urls: Vec<ModrinthProject>,
     dest_dir: impl AsRef<Path>,
-) -> Result<Vec<PathBuf>> {
-    const CONCURRENCY: usize = 8;
-
+    config: &Config,
+    cache: Option<Arc<Cache>>,
+) -> Result<Vec<DownloadResult>> {
     let dest_dir = dest_dir.as_ref().to_path_buf();
     fs::create_dir_all(&dest_dir).await?;
 
+    if let Some(cache) = &cache {
+        cache.register_pack(&dest_dir).await?;
+    }
+
     let client = Arc::new(reqwest::Client::new());
-    let mp = Arc::new(MultiProgress::new());
-    let sem = Arc::new(Semaphore::new(CONCURRENCY));
+    let mp = Arc::new(MultiProgress::with_draw_target(output::progress_target()));
+    let offline = config.offline;
+    let config = &config.download;
+    let sem = Arc::new(Semaphore::new(config.concurrency.max(1)));
+    let throttle = match config.bandwidth_limit {
+        0 => None,
+        limit => Some(Arc::new(Throttle::new(limit))),
+    };
 
     let mut tasks = FuturesUnordered::new();
 
@@ -30,84 +141,414 @@ pub async fn download_files(
         let client = Arc::clone(&client);
         let mp = Arc::clone(&mp);
         let sem = Arc::clone(&sem);
+        let throttle = throttle.clone();
+        let cache = cache.clone();
         let dest_dir = dest_dir.clone();
+        let config = config.clone();
 
-        tasks.push(tokio::spawn(async move {
+        // The project stays next to its task, so a task that panics still gets an outcome
+        let project = url.clone();
+        let task = tokio::spawn(async move {
             let _permit = sem.acquire().await.unwrap();
 
-            let url_parsed = Url::parse(&url.download_link)?;
-            let resp = client
-                .get(url_parsed.clone())
-                .header(
-                    USER_AGENT,
-                    "kay-xr/modrinth_collection_downloader/0.1.0 (archangel@angelware.net)",
+            let mut attempts = 0;
+            let outcome = loop {
+                attempts += 1;
+                match download_file(
+                    &client,
+                    &url,
+                    &dest_dir,
+                    &mp,
+                    throttle.as_deref(),
+                    cache.as_deref(),
+                    offline,
                 )
-                .send()
-                .await?
-                .error_for_status()?;
-
-            let filename = filename_from_response(&url_parsed, &resp);
-            let filepath = dest_dir.join(&filename);
-
-            // indicatif bar
-            let total = resp.content_length();
-            let pb = mp.add(match total {
-                Some(n) => ProgressBar::new(n),
-                None => ProgressBar::new_spinner(),
-            });
-
-            pb.set_style(
-                ProgressStyle::with_template(
-                    "{spinner:.green} {msg:.dim} {bytes:>10}/{total_bytes:10} ({eta})\n{bar:40.cyan/blue}",
-                )?
-            );
-            pb.set_message(filename.clone());
-
-            let mut file = File::create(&filepath).await?;
-            let mut stream = resp.bytes_stream();
-
-            while let Some(chunk) = stream.next().await {
-                let chunk = chunk?;
-                file.write_all(&chunk).await?;
-                pb.inc(chunk.len() as u64);
+                .await
+                {
+                    Ok(outcome) => break outcome,
+                    Err(e) if attempts <= config.retries && is_retryable(&e) => {
+                        let delay = config
+                            .backoff_ms
+                            .saturating_mul(1 << (attempts - 1).min(16));
+                        tokio::time::sleep(Duration::from_millis(delay)).await;
+                    }
+                    Err(e) => {
+                        // Out of attempts, the partial file is of no use anymore
+                        if let Ok(name) = file_name(&url) {
+                            let _ = fs::remove_file(part_path(&dest_dir, &name)).await;
+                        }
+
+                        match e.downcast::<HashMismatch>() {
+                            Ok(mismatch) => {
+                                break DownloadOutcome::HashMismatch {
+                                    expected: mismatch.expected,
+                                    actual: mismatch.actual,
+                                };
+                            }
+                            Err(e) => {
+                                break DownloadOutcome::Failed {
+                                    reason: format!("{e:#}"),
+                                    attempts,
+                                };
+                            }
+                        }
+                    }
+                }
+            };
+
+            DownloadResult {
+                project: url,
+                outcome,
             }
+        });
+        tasks.push(async move { (project, task.await) });
+    }
+
+    // run all
+    let mut results = Vec::new();
+    while let Some((project, res)) = tasks.next().await {
+        let result = res.unwrap_or_else(|join_err| DownloadResult {
+            project,
+            outcome: DownloadOutcome::Failed {
+                reason: format!("Download task failed: {join_err}"),
+                attempts: 1,
+            },
+        });
+        output::event("download", &result);
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// How many downloads ended in each outcome.
+#[derive(Serialize)]
+pub struct Summary {
+    pub downloaded: usize,
+    pub up_to_date: usize,
+    pub cached: usize,
+    pub failed: usize,
+    pub hash_mismatch: usize,
+}
+
+impl Summary {
+    pub fn of(results: &[DownloadResult]) -> Summary {
+        let count =
+            |f: fn(&DownloadOutcome) -> bool| results.iter().filter(|r| f(&r.outcome)).count();
+        Summary {
+            downloaded: count(|o| matches!(o, DownloadOutcome::Downloaded { .. })),
+            up_to_date: count(|o| matches!(o, DownloadOutcome::UpToDate { .. })),
+            cached: count(|o| matches!(o, DownloadOutcome::Cached { .. })),
+            failed: count(|o| matches!(o, DownloadOutcome::Failed { .. })),
+            hash_mismatch: count(|o| matches!(o, DownloadOutcome::HashMismatch { .. })),
+        }
+    }
+}
+
+/// Prints a table with the outcome of every download, followed by the totals.
+pub fn print_summary(results: &[DownloadResult]) {
+    let width = results
+        .iter()
+        .map(|r| r.project.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("File".len());
+
+    say!("{:<width$}  {:<10}  Status", "File", "Project");
+    for result in results {
+        let status = match &result.outcome {
+            DownloadOutcome::Downloaded { .. } => "downloaded".bright_green(),
+            DownloadOutcome::UpToDate { .. } => "up to date".bright_green(),
+            DownloadOutcome::Cached { .. } => "from cache".bright_green(),
+            DownloadOutcome::Failed { reason, attempts } => {
+                format!("failed after {attempts} attempt(s): {reason}").bright_red()
+            }
+            DownloadOutcome::HashMismatch { .. } => "hash mismatch".bright_red(),
+        };
+        say!(
+            "{:<width$}  {:<10}  {}",
+            result.project.name,
+            result.project.id,
+            status
+        );
+    }
+
+    let summary = Summary::of(results);
+    say!(
+        "{}",
+        format!(
+            "Downloaded: {}, up to date: {}, from cache: {}, failed: {}, hash mismatch: {}",
+            summary.downloaded,
+            summary.up_to_date,
+            summary.cached,
+            summary.failed,
+            summary.hash_mismatch,
+        )
+        .bright_green()
+    );
+    output::event("summary", &summary);
+}
+
+/// Timeouts, dropped connections, rate limits and server errors are worth another attempt.
+/// Anything else, like a 404, a hash mismatch or a disk error, fails the file straight away.
+fn is_retryable(err: &anyhow::Error) -> bool {
+    if let Some(e) = err.downcast_ref::<reqwest::Error>() {
+        return match e.status() {
+            Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+            None => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+        };
+    }
+
+    if let Some(e) = err.downcast_ref::<std::io::Error>() {
+        return matches!(
+            e.kind(),
+            ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::TimedOut
+                | ErrorKind::UnexpectedEof
+        );
+    }
+
+    false
+}
 
+/// Downloads a single file into `<name>.part`, and only renames it to the final name once it is
+/// complete and its hash matches. Files already on disk with the right hash are skipped. The
+/// partial file is kept when the failure is retryable so the next attempt can resume it, and
+/// removed otherwise. A resumed file that fails its hash check is downloaded once more from the
+/// start.
+async fn download_file(
+    client: &Client,
+    project: &ModrinthProject,
+    dest_dir: &Path,
+    mp: &MultiProgress,
+    throttle: Option<&Throttle>,
+    cache: Option<&Cache>,
+    offline: bool,
+) -> Result<DownloadOutcome> {
+    let url_parsed = Url::parse(&project.download_link)?;
+    let filename = file_name(project)?;
+    let filepath = dest_dir.join(&filename);
+    let part_path = part_path(dest_dir, &filename);
+
+    if is_up_to_date(project, &filepath).await? {
+        return Ok(DownloadOutcome::UpToDate { path: filepath });
+    }
+
+    if let Some(cache) = cache
+        && cache.link_into(&project.sha512, &filepath).await?
+    {
+        return Ok(DownloadOutcome::Cached { path: filepath });
+    }
+
+    if offline {
+        return Err(anyhow!(
+            "{filename} was not prefetched, run the prefetch command while online first"
+        ));
+    }
+
+    // indicatif bar
+    let pb = mp.add(ProgressBar::new_spinner());
+    pb.set_style(ProgressStyle::with_template(
+        "{spinner:.green} {msg:.dim} {bytes:>10}/{total_bytes:10} ({eta})\n{bar:40.cyan/blue}",
+    )?);
+    pb.set_message(filename.clone());
+
+    let fetch =
+        async || match download_part(client, url_parsed.clone(), &part_path, &pb, throttle).await {
+            Ok(()) => verify_hash(&part_path, &project.sha512).await,
+            Err(e) => Err(e),
+        };
+
+    let resumed = fs::try_exists(&part_path).await?;
+    let mut result = fetch().await;
+    // The leftover part may be from another version of the file, so start over once
+    if resumed && result.as_ref().is_err_and(|e| e.is::<HashMismatch>()) {
+        let _ = fs::remove_file(&part_path).await;
+        pb.set_position(0);
+        result = fetch().await;
+    }
+
+    match result {
+        Ok(()) => {
+            fs::rename(&part_path, &filepath).await?;
+            if let Some(cache) = cache
+                && let Err(e) = cache.store(&project.sha512, &filepath).await
+            {
+                pb.println(format!("Could not cache {filename}: {e}"));
+            }
             pb.finish_with_message(format!("{} ✓", filename));
-            Ok::<PathBuf, anyhow::Error>(filepath)
-        }));
+            Ok(DownloadOutcome::Downloaded { path: filepath })
+        }
+        Err(e) => {
+            if !is_retryable(&e) {
+                let _ = fs::remove_file(&part_path).await;
+            }
+            pb.abandon_with_message(format!("{} ✗", filename));
+            Err(e)
+        }
     }
+}
 
-    // run all
-    let mut saved = Vec::new();
-    while let Some(res) = tasks.next().await {
-        match res {
-            Ok(Ok(path)) => saved.push(path),
-            Ok(Err(e)) => eprintln!("Download failed: {e}"),
-            Err(join_err) => eprintln!("Task join error: {join_err}"),
+/// What [`download_files`] would do with a project, without touching the disk.
+pub async fn plan_download(
+    project: &ModrinthProject,
+    dest_dir: &Path,
+    cache: Option<&Cache>,
+) -> Result<PlannedAction> {
+    let filepath = dest_dir.join(file_name(project)?);
+
+    if is_up_to_date(project, &filepath).await? {
+        Ok(PlannedAction::Skip)
+    } else if let Some(cache) = cache
+        && cache.contains(&project.sha512).await?
+    {
+        Ok(PlannedAction::Cache)
+    } else {
+        Ok(PlannedAction::Download)
+    }
+}
+
+/// File name a project is saved as, the one Modrinth reports or else the last URL segment.
+pub fn file_name(project: &ModrinthProject) -> Result<String> {
+    if project.name.is_empty() {
+        Ok(filename_from_url(&Url::parse(&project.download_link)?))
+    } else {
+        Ok(project.name.clone())
+    }
+}
+
+/// Where a file is downloaded to before it is complete.
+fn part_path(dest_dir: &Path, filename: &str) -> PathBuf {
+    dest_dir.join(format!("{filename}.part"))
+}
+
+/// Whether the file is already on disk with the expected hash.
+async fn is_up_to_date(project: &ModrinthProject, filepath: &Path) -> Result<bool> {
+    Ok(!project.sha512.is_empty()
+        && fs::try_exists(filepath).await?
+        && verify_hash(filepath, &project.sha512).await.is_ok())
+}
+
+/// Compares the sha512 of the downloaded file against the one reported by Modrinth.
+async fn verify_hash(path: &Path, expected: &str) -> Result<()> {
+    if expected.is_empty() {
+        return Ok(());
+    }
+
+    let actual = sha512_file(path).await?;
+    if actual.eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(HashMismatch {
+            expected: expected.to_string(),
+            actual,
         }
+        .into())
+    }
+}
+
+/// Hex encoded sha512 of a file on disk.
+pub async fn sha512_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path).await?;
+    let mut hasher = Sha512::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
     }
 
-    Ok(saved)
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
-/// Trying to extract the filename from header or fallback to the URL
-fn filename_from_response(url: &Url, resp: &reqwest::Response) -> String {
-    if let Some(disposition) = resp.headers().get(reqwest::header::CONTENT_DISPOSITION) {
-        if let Ok(s) = disposition.to_str() {
-            if let Some(idx) = s.to_ascii_lowercase().find("filename=") {
-                let mut v = s[idx + "filename=".len()..].trim().trim_matches(';').trim();
-                v = v.trim_matches('"');
-                if !v.is_empty() {
-                    return v.to_string();
+/// Streams the file into the part path. If a `.part` already exists, or the connection drops,
+/// the download continues from the current length with a `Range` request. Servers that ignore
+/// the range answer with a full `200` body, in which case we start over.
+async fn download_part(
+    client: &Client,
+    url: Url,
+    part_path: &Path,
+    pb: &ProgressBar,
+    throttle: Option<&Throttle>,
+) -> Result<()> {
+    let mut resumes = 0;
+
+    loop {
+        let offset = match fs::metadata(part_path).await {
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        };
+
+        let mut req = client.get(url.clone()).header(
+            USER_AGENT,
+            "kay-xr/modrinth_collection_downloader/0.1.0 (archangel@angelware.net)",
+        );
+        if offset > 0 {
+            req = req.header(RANGE, format!("bytes={offset}-"));
+        }
+        let resp = req.send().await?;
+
+        // Leftover part is larger than the file, throw it away
+        if resp.status() == StatusCode::RANGE_NOT_SATISFIABLE && resumes < MAX_RESUMES {
+            fs::remove_file(part_path).await?;
+            resumes += 1;
+            continue;
+        }
+        let resp = resp.error_for_status()?;
+
+        let resumed = offset > 0 && resp.status() == StatusCode::PARTIAL_CONTENT;
+        let (mut file, start) = if resumed {
+            (
+                OpenOptions::new().append(true).open(part_path).await?,
+                offset,
+            )
+        } else {
+            (File::create(part_path).await?, 0)
+        };
+
+        if let Some(len) = resp.content_length() {
+            pb.set_length(start + len);
+        }
+        pb.set_position(start);
+
+        let mut stream = resp.bytes_stream();
+        let mut interrupted = None;
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(chunk) => {
+                    if let Some(throttle) = throttle {
+                        throttle.consume(chunk.len()).await;
+                    }
+                    file.write_all(&chunk).await?;
+                    pb.inc(chunk.len() as u64);
+                }
+                Err(e) => {
+                    interrupted = Some(e);
+                    break;
                 }
             }
         }
+        file.flush().await?;
+
+        match interrupted {
+            None => return Ok(()),
+            Some(_) if resumes < MAX_RESUMES => resumes += 1,
+            Some(e) => {
+                return Err(anyhow::Error::from(e)
+                    .context(format!("connection dropped after {resumes} resumes")));
+            }
+        }
     }
+}
 
-    // fallback, use url
+/// Extracts the filename from the last URL segment
+fn filename_from_url(url: &Url) -> String {
     let raw = url
         .path_segments()
-        .and_then(|segment| segment.last().map(|s| s.to_string()))
+        .and_then(|mut segment| segment.next_back().map(|s| s.to_string()))
         .filter(|s| !s.is_empty())
         .unwrap_or_else(|| "download".to_string());
 
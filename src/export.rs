@@ -0,0 +1,465 @@
+// Mod lists for publishing a pack, as CSV, a Markdown table or a static HTML credits page, and zip
+// bundles of the files themselves.
+
+use crate::log::ModrinthLog;
+use crate::modrinth::{
+    ApiClient, License, Project, get_organization_name, get_projects, get_teams,
+};
+use anyhow::{Context, Result};
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tokio::fs;
+
+/// Format of an exported mod list.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Markdown,
+    Html,
+    /// The downloaded files themselves, with the Markdown list as credits.
+    Zip,
+}
+
+impl ExportFormat {
+    /// File written next to `collection.json` when no output is given.
+    pub fn default_file_name(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "modlist.csv",
+            ExportFormat::Markdown => "modlist.md",
+            ExportFormat::Html => "credits.html",
+            ExportFormat::Zip => "pack.zip",
+        }
+    }
+
+    /// Whether the export contains the jars instead of linking to them.
+    pub fn bundles_files(self) -> bool {
+        matches!(self, ExportFormat::Zip)
+    }
+}
+
+/// Every mod of a pack with the details needed to credit it.
+#[derive(Serialize)]
+pub struct ModList {
+    pub name: String,
+    pub loader: String,
+    pub game_version: String,
+    pub mods: Vec<ModListEntry>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ModListEntry {
+    pub id: String,
+    pub title: String,
+    pub slug: String,
+    pub version: String,
+    /// Project page on Modrinth.
+    pub url: String,
+    pub file_name: String,
+    pub license: Option<License>,
+    pub authors: Vec<String>,
+}
+
+impl ModList {
+    /// Builds the list from the files a run downloaded, with licenses and authors looked up on
+    /// Modrinth. Files that failed to download are left out.
+    pub async fn from_log(api: &ApiClient, log: &ModrinthLog) -> Result<ModList> {
+        let mut seen = HashSet::new();
+        let entries: Vec<_> = log
+            .entries
+            .iter()
+            .filter(|e| e.outcome.is_success())
+            .filter(|e| seen.insert(e.id.clone()))
+            .collect();
+
+        let ids: Vec<String> = entries.iter().map(|e| e.id.clone()).collect();
+        let projects: HashMap<String, Project> = get_projects(api, &ids)
+            .await
+            .context("Getting project details failed: ")?
+            .into_iter()
+            .map(|p| (p.id.clone(), p))
+            .collect();
+        let authors = authors(api, projects.values()).await?;
+
+        let mut mods: Vec<ModListEntry> = entries
+            .into_iter()
+            .map(|entry| {
+                let project = projects.get(&entry.id);
+                let slug = project.map_or(entry.slug.clone(), |p| p.slug.clone());
+                ModListEntry {
+                    id: entry.id.clone(),
+                    title: project.map_or(entry.title.clone(), |p| p.title.clone()),
+                    url: match project {
+                        Some(p) => format!("https://modrinth.com/{}/{}", p.project_type, p.slug),
+                        None => format!("https://modrinth.com/project/{}", entry.id),
+                    },
+                    slug,
+                    version: entry.version_number.clone(),
+                    file_name: entry.file_name.clone(),
+                    license: project.and_then(|p| p.license.clone()),
+                    authors: authors.get(&entry.id).cloned().unwrap_or_default(),
+                }
+            })
+            .collect();
+        mods.sort_by_key(|m| m.title.to_lowercase());
+
+        let names: Vec<&str> = log.collections.iter().map(|c| c.name.as_str()).collect();
+        Ok(ModList {
+            name: if names.is_empty() {
+                "Mod list".to_string()
+            } else {
+                names.join(" + ")
+            },
+            loader: log.loader.clone(),
+            game_version: log.game_version.clone(),
+            mods,
+        })
+    }
+
+    /// Writes the list to `file`. Zip bundles take the files from where `log` says they were
+    /// downloaded, keeping their path relative to `root`.
+    pub async fn write(
+        &self,
+        log: &ModrinthLog,
+        format: ExportFormat,
+        root: &Path,
+        file: &Path,
+    ) -> Result<()> {
+        if format.bundles_files() {
+            self.bundle(log, root, file).await
+        } else {
+            fs::write(file, self.render(format)).await?;
+            Ok(())
+        }
+    }
+
+    /// Text of the list, bundles carry the Markdown one.
+    pub fn render(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Csv => self.to_csv(),
+            ExportFormat::Markdown | ExportFormat::Zip => self.to_markdown(),
+            ExportFormat::Html => self.to_html(),
+        }
+    }
+
+    async fn bundle(&self, log: &ModrinthLog, root: &Path, file: &Path) -> Result<()> {
+        let mut writer = ZipFileWriter::with_tokio(fs::File::create(file).await?);
+
+        let mut written = HashSet::new();
+        for entry in &log.entries {
+            let Some(path) = entry.outcome.path() else {
+                continue;
+            };
+            let name = match path.strip_prefix(root) {
+                Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+                Err(_) => format!("mods/{}", entry.file_name),
+            };
+            if !written.insert(name.clone()) {
+                continue;
+            }
+
+            let data = fs::read(path)
+                .await
+                .with_context(|| format!("reading {}", path.display()))?;
+            writer
+                .write_entry_whole(
+                    ZipEntryBuilder::new(name.into(), Compression::Deflate),
+                    &data,
+                )
+                .await?;
+        }
+
+        writer
+            .write_entry_whole(
+                ZipEntryBuilder::new("modlist.md".to_string().into(), Compression::Deflate),
+                self.to_markdown().as_bytes(),
+            )
+            .await?;
+        writer.close().await?;
+
+        Ok(())
+    }
+
+    fn to_csv(&self) -> String {
+        let mut out = String::from("title,version,url,license,license_url,authors,file_name\n");
+        for m in &self.mods {
+            let fields = [
+                m.title.as_str(),
+                &m.version,
+                &m.url,
+                &license_label(m.license.as_ref()),
+                license_url(m.license.as_ref()).unwrap_or_default(),
+                &m.authors.join(", "),
+                &m.file_name,
+            ];
+            let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+            out.push_str(&row.join(","));
+            out.push('\n');
+        }
+        out
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = format!(
+            "# {}\n\n{} mods for {} on Minecraft {}.\n\n",
+            markdown_cell(&self.name),
+            self.mods.len(),
+            self.loader,
+            self.game_version
+        );
+        out.push_str("| Title | Version | Link | License | Authors |\n");
+        out.push_str("| --- | --- | --- | --- | --- |\n");
+        for m in &self.mods {
+            let license = markdown_cell(&license_label(m.license.as_ref()));
+            let license = match license_url(m.license.as_ref()) {
+                Some(url) => format!("[{license}]({})", markdown_url(url)),
+                None => license,
+            };
+            out.push_str(&format!(
+                "| {} | {} | [{}]({}) | {} | {} |\n",
+                markdown_cell(&m.title),
+                markdown_cell(&m.version),
+                markdown_cell(&m.slug),
+                m.url,
+                license,
+                markdown_cell(&m.authors.join(", "))
+            ));
+        }
+        out
+    }
+
+    fn to_html(&self) -> String {
+        let mut rows = String::new();
+        for m in &self.mods {
+            let license = html_escape(&license_label(m.license.as_ref()));
+            let license = match license_url(m.license.as_ref()) {
+                Some(url) => format!("<a href=\"{}\">{license}</a>", html_escape(url)),
+                None => license,
+            };
+            rows.push_str(&format!(
+                "      <tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                html_escape(&m.url),
+                html_escape(&m.title),
+                html_escape(&m.version),
+                html_escape(&m.authors.join(", ")),
+                license
+            ));
+        }
+
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>{name} credits</title>
+  <style>
+    body {{ font-family: sans-serif; max-width: 960px; margin: 2em auto; padding: 0 1em; }}
+    table {{ border-collapse: collapse; width: 100%; }}
+    th, td {{ text-align: left; padding: 0.4em 0.6em; border-bottom: 1px solid #ddd; }}
+  </style>
+</head>
+<body>
+  <h1>{name}</h1>
+  <p>{count} mods for {loader} on Minecraft {game_version}. Thanks to all of their authors!</p>
+  <table>
+    <thead>
+      <tr><th>Mod</th><th>Version</th><th>Authors</th><th>License</th></tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+</body>
+</html>
+"#,
+            name = html_escape(&self.name),
+            count = self.mods.len(),
+            loader = html_escape(&self.loader),
+            game_version = html_escape(&self.game_version),
+        )
+    }
+}
+
+/// Author names per project id. Projects owned by an organization are credited to it when their
+/// team has no members of its own.
+async fn authors<'a>(
+    api: &ApiClient,
+    projects: impl Iterator<Item = &'a Project>,
+) -> Result<HashMap<String, Vec<String>>> {
+    let projects: Vec<&Project> = projects.collect();
+    let team_ids: Vec<String> = projects.iter().map(|p| p.team.clone()).collect();
+
+    let mut members: HashMap<String, Vec<String>> = HashMap::new();
+    for member in get_teams(api, &team_ids).await?.into_iter().flatten() {
+        members
+            .entry(member.team_id)
+            .or_default()
+            .push(member.user.username);
+    }
+
+    let mut authors = HashMap::new();
+    for project in projects {
+        let mut names = members.get(&project.team).cloned().unwrap_or_default();
+        if names.is_empty()
+            && let Some(organization) = &project.organization
+            && let Ok(name) = get_organization_name(api, organization).await
+        {
+            names.push(name);
+        }
+        authors.insert(project.id.clone(), names);
+    }
+
+    Ok(authors)
+}
+
+/// Short license name, custom licenses use their name instead of the `LicenseRef-` id.
+pub fn license_label(license: Option<&License>) -> String {
+    match license {
+        None => "Unknown".to_string(),
+        Some(license) if license.id.starts_with("LicenseRef-") => {
+            if license.name.is_empty() {
+                license.id["LicenseRef-".len()..].replace('-', " ")
+            } else {
+                license.name.clone()
+            }
+        }
+        Some(license) => license.id.clone(),
+    }
+}
+
+/// The license URL if it is a web link. Authors can set anything here, so other schemes like
+/// `javascript:` are never linked.
+fn license_url(license: Option<&License>) -> Option<&str> {
+    license.and_then(|l| l.url.as_deref()).filter(|url| {
+        let url = url.to_ascii_lowercase();
+        url.starts_with("https://") || url.starts_with("http://")
+    })
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Keeps a value inside its table cell.
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Percent-encodes the characters that would end a Markdown link target or its table cell.
+fn markdown_url(url: &str) -> String {
+    url.replace('(', "%28")
+        .replace(')', "%29")
+        .replace(' ', "%20")
+        .replace('|', "%7C")
+        .replace('<', "%3C")
+        .replace('>', "%3E")
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(title: &str, license: Option<License>) -> ModList {
+        ModList {
+            name: "Pack".to_string(),
+            loader: "fabric".to_string(),
+            game_version: "1.21.1".to_string(),
+            mods: vec![ModListEntry {
+                id: "AANobbMI".to_string(),
+                title: title.to_string(),
+                slug: "sodium".to_string(),
+                version: "0.6.0".to_string(),
+                url: "https://modrinth.com/mod/sodium".to_string(),
+                file_name: "sodium.jar".to_string(),
+                license,
+                authors: vec!["jellysquid3".to_string(), "IMS".to_string()],
+            }],
+        }
+    }
+
+    #[test]
+    fn escapes_csv_and_markdown() {
+        let list = list("Sodium, \"fast\" | pretty", None);
+
+        let csv = list.render(ExportFormat::Csv);
+        assert_eq!(
+            csv.lines().nth(1),
+            Some(
+                "\"Sodium, \"\"fast\"\" | pretty\",0.6.0,https://modrinth.com/mod/sodium,Unknown,,\"jellysquid3, IMS\",sodium.jar"
+            )
+        );
+
+        let markdown = list.render(ExportFormat::Markdown);
+        assert!(markdown.contains("| Sodium, \"fast\" \\| pretty | 0.6.0 |"));
+    }
+
+    #[test]
+    fn escapes_html() {
+        let html = list("<Sodium> & co", None).render(ExportFormat::Html);
+        assert!(html.contains("&lt;Sodium&gt; &amp; co"));
+        assert!(!html.contains("<Sodium>"));
+    }
+
+    #[test]
+    fn links_only_web_license_urls() {
+        let license = |url: &str| {
+            Some(License {
+                id: "LicenseRef-Custom".to_string(),
+                name: "Custom".to_string(),
+                url: Some(url.to_string()),
+            })
+        };
+
+        let markdown =
+            list("Sodium", license("https://example.com/a_(b)|c")).render(ExportFormat::Markdown);
+        assert!(markdown.contains("[Custom](https://example.com/a_%28b%29%7Cc)"));
+
+        for url in [
+            "javascript:alert(1)",
+            "JavaScript:alert(1)",
+            "data:text/html,x",
+        ] {
+            let list = list("Sodium", license(url));
+            let html = list.render(ExportFormat::Html);
+            assert!(!html.contains("alert") && !html.contains("data:"));
+            assert!(html.contains("<td>Custom</td>"));
+            let markdown = list.render(ExportFormat::Markdown);
+            assert!(markdown.contains("| Custom |"));
+        }
+    }
+
+    #[test]
+    fn labels_licenses() {
+        let license = |id: &str, name: &str| License {
+            id: id.to_string(),
+            name: name.to_string(),
+            url: None,
+        };
+
+        assert_eq!(license_label(None), "Unknown");
+        assert_eq!(license_label(Some(&license("MIT", "MIT License"))), "MIT");
+        assert_eq!(
+            license_label(Some(&license("LicenseRef-All-Rights-Reserved", ""))),
+            "All Rights Reserved"
+        );
+        assert_eq!(
+            license_label(Some(&license("LicenseRef-Custom", "My License"))),
+            "My License"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+// License audit, which mods of a pack may be redistributed.
+
+use crate::export::{ModList, license_label};
+use crate::modrinth::License;
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Modrinth's id for "All Rights Reserved".
+const ALL_RIGHTS_RESERVED: &str = "LicenseRef-All-Rights-Reserved";
+
+/// What happens when an export that bundles jars contains mods that are all rights reserved or
+/// have no known license.
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LicensePolicy {
+    /// Bundle them without comment.
+    Allow,
+    /// List them and bundle them anyway.
+    #[default]
+    Warn,
+    /// Refuse to write the export.
+    Block,
+}
+
+/// Mods grouped by license.
+#[derive(Serialize)]
+pub struct LicenseReport {
+    pub groups: Vec<LicenseGroup>,
+}
+
+#[derive(Serialize)]
+pub struct LicenseGroup {
+    /// SPDX id, `None` when the project has no license set.
+    pub id: Option<String>,
+    pub label: String,
+    pub url: Option<String>,
+    /// All rights reserved or unknown, redistributing these needs the author's permission.
+    pub restricted: bool,
+    /// Titles of the mods under this license.
+    pub mods: Vec<String>,
+}
+
+impl LicenseReport {
+    /// Groups are sorted by size, restricted ones go last so they stay visible.
+    pub fn of(list: &ModList) -> LicenseReport {
+        let mut groups: BTreeMap<Option<String>, LicenseGroup> = BTreeMap::new();
+        for m in &list.mods {
+            let license = m.license.as_ref().filter(|l| !l.id.is_empty());
+            groups
+                .entry(license.map(|l| l.id.clone()))
+                .or_insert_with(|| LicenseGroup {
+                    id: license.map(|l| l.id.clone()),
+                    label: license_label(license),
+                    url: license.and_then(|l| l.url.clone()),
+                    restricted: is_restricted(license),
+                    mods: Vec::new(),
+                })
+                .mods
+                .push(m.title.clone());
+        }
+
+        let mut groups: Vec<LicenseGroup> = groups.into_values().collect();
+        groups.sort_by_key(|g| (g.restricted, std::cmp::Reverse(g.mods.len())));
+        LicenseReport { groups }
+    }
+
+    /// Titles of every restricted mod.
+    pub fn restricted(&self) -> Vec<&str> {
+        self.groups
+            .iter()
+            .filter(|g| g.restricted)
+            .flat_map(|g| g.mods.iter().map(String::as_str))
+            .collect()
+    }
+
+    pub fn print(&self) {
+        for group in &self.groups {
+            let heading = format!("{} ({})", group.label, group.mods.len());
+            if group.restricted {
+                say!("{}", heading.bright_red());
+            } else {
+                say!("{}", heading.bright_green());
+            }
+            if let Some(url) = &group.url {
+                say!("  {url}");
+            }
+            for title in &group.mods {
+                say!("  - {title}");
+            }
+        }
+
+        let restricted = self.restricted();
+        if !restricted.is_empty() {
+            say!(
+                "{}",
+                format!(
+                    "{} mods are all rights reserved or have no known license, ask their authors before redistributing them",
+                    restricted.len()
+                )
+                .bright_red()
+            );
+        }
+    }
+
+    /// Applies `policy` before writing an export that bundles the jars themselves.
+    pub fn check(&self, policy: LicensePolicy) -> Result<()> {
+        let restricted = self.restricted();
+        if restricted.is_empty() {
+            return Ok(());
+        }
+
+        match policy {
+            LicensePolicy::Allow => {}
+            LicensePolicy::Warn => say!(
+                "{}",
+                format!(
+                    "Bundling mods that may not allow redistribution: {}",
+                    restricted.join(", ")
+                )
+                .bright_red()
+            ),
+            LicensePolicy::Block => bail!(
+                "Not bundling mods that may not allow redistribution: {}. Link them instead, or use --license-policy warn",
+                restricted.join(", ")
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// No license, an unknown one or all rights reserved.
+pub fn is_restricted(license: Option<&License>) -> bool {
+    license.is_none_or(|l| {
+        l.id.is_empty() || l.id == ALL_RIGHTS_RESERVED || l.id == "LicenseRef-Unknown"
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ModListEntry;
+
+    fn entry(title: &str, license: Option<&str>) -> ModListEntry {
+        ModListEntry {
+            id: title.to_string(),
+            title: title.to_string(),
+            slug: title.to_lowercase(),
+            version: "1.0.0".to_string(),
+            url: String::new(),
+            file_name: format!("{title}.jar"),
+            license: license.map(|id| License {
+                id: id.to_string(),
+                name: String::new(),
+                url: None,
+            }),
+            authors: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn groups_and_flags_restricted_mods() {
+        let list = ModList {
+            name: "Pack".to_string(),
+            loader: "fabric".to_string(),
+            game_version: "1.21.1".to_string(),
+            mods: vec![
+                entry("Sodium", Some("LGPL-3.0-only")),
+                entry("Closed", Some(ALL_RIGHTS_RESERVED)),
+                entry("Lithium", Some("LGPL-3.0-only")),
+                entry("Mystery", None),
+                entry("Tiny", Some("MIT")),
+            ],
+        };
+
+        let report = LicenseReport::of(&list);
+        let labels: Vec<&str> = report.groups.iter().map(|g| g.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            ["LGPL-3.0-only", "MIT", "Unknown", "All Rights Reserved"]
+        );
+        assert_eq!(report.restricted(), ["Mystery", "Closed"]);
+
+        assert!(report.check(LicensePolicy::Warn).is_ok());
+        assert!(report.check(LicensePolicy::Block).is_err());
+    }
+}
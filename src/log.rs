@@ -1,21 +1,92 @@
-use crate::modrinth::ModrinthProject;
-use anyhow::Result;
-use serde::Serialize;
-use std::path::PathBuf;
+use crate::compat::FailureReport;
+use crate::download::{DownloadOutcome, DownloadResult};
+use crate::modrinth::{ApiClient, Dependency, ModrinthProject, Project, get_projects};
+use crate::sources::CollectionRef;
+use crate::store::now;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use tokio::fs;
 
+/// Version of the `collection.json` layout. It is bumped when a field changes meaning or goes
+/// away, not when fields are added. Version 1 only had `ids`, `projects`, `failed_ids`,
+/// `downloads` and `sources`, which are all still written.
+pub const LOG_SCHEMA_VERSION: u32 = 2;
+
+/// Everything about a run that isn't tied to a single file.
+pub struct RunInfo {
+    pub loader: String,
+    pub game_version: String,
+    pub collections: Vec<CollectionRef>,
+    /// Maps every project id to the collections/projects it came from.
+    pub sources: BTreeMap<String, Vec<String>>,
+    /// Projects without a compatible version.
+    pub failures: Vec<LogFailure>,
+}
+
 /// Creates a log of all mod projects & ids in a json file. Useful for packwiz, etc.
+/// Only successfully downloaded projects end up in `ids` and `projects`, every outcome is kept
+/// in `downloads` and `entries`. Titles and slugs are looked up on Modrinth.
 pub async fn create_log_file(
-    links: Vec<ModrinthProject>,
-    failed: Vec<String>,
+    api: &ApiClient,
+    results: Vec<DownloadResult>,
+    info: RunInfo,
     path: PathBuf,
 ) -> Result<()> {
     let file_path = path.join("collection.json");
 
+    let links: Vec<ModrinthProject> = results
+        .iter()
+        .filter(|r| r.outcome.is_success())
+        .map(|r| r.project.clone())
+        .collect();
+
+    // Titles are nice to have, a failed lookup (e.g. offline) leaves them empty
+    let ids: Vec<String> = results.iter().map(|r| r.project.id.clone()).collect();
+    let projects: HashMap<String, Project> = get_projects(api, &ids)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|p| (p.id.clone(), p))
+        .collect();
+
+    let entries = results
+        .iter()
+        .map(|r| {
+            let project = projects.get(&r.project.id);
+            LogEntry {
+                id: r.project.id.clone(),
+                title: project.map(|p| p.title.clone()).unwrap_or_default(),
+                slug: project.map(|p| p.slug.clone()).unwrap_or_default(),
+                version_id: r.project.version_id.clone(),
+                version_number: r.project.version_number.clone(),
+                file_name: r.project.name.clone(),
+                url: r.project.download_link.clone(),
+                size: r.project.size,
+                sha1: r.project.sha1.clone(),
+                sha512: r.project.sha512.clone(),
+                dependencies: r.project.dependencies.clone(),
+                sources: info.sources.get(&r.project.id).cloned().unwrap_or_default(),
+                outcome: r.outcome.clone(),
+            }
+        })
+        .collect();
+
     let collection_log: ModrinthLog = ModrinthLog {
+        schema_version: LOG_SCHEMA_VERSION,
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        generated_at: now(),
+        loader: info.loader,
+        game_version: info.game_version,
+        collections: info.collections,
         ids: links.iter().map(|proj| proj.id.clone()).collect(),
         projects: links,
-        failed_ids: failed,
+        failed_ids: info.failures.iter().map(|f| f.id.clone()).collect(),
+        downloads: results,
+        sources: info.sources,
+        entries,
+        failures: info.failures,
     };
 
     let toml_str = serde_json::to_string_pretty(&collection_log)?;
@@ -24,9 +95,192 @@ pub async fn create_log_file(
     Ok(())
 }
 
-#[derive(Serialize)]
+/// Reads the `collection.json` in `path`, `None` if there is none.
+pub async fn read_log(path: &Path) -> Result<Option<ModrinthLog>> {
+    let log_path = path.join("collection.json");
+    if !fs::try_exists(&log_path).await? {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(&log_path).await?;
+    let log = serde_json::from_str(&contents)
+        .with_context(|| format!("parsing {}", log_path.display()))?;
+    Ok(Some(log))
+}
+
+/// Fields missing from older logs are left empty when reading them.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct ModrinthLog {
+    pub schema_version: u32,
+    /// Version of this tool that wrote the log.
+    pub tool_version: String,
+    /// Unix timestamp in seconds.
+    pub generated_at: u64,
+    pub loader: String,
+    pub game_version: String,
+    pub collections: Vec<CollectionRef>,
     pub ids: Vec<String>,
     pub projects: Vec<ModrinthProject>,
     pub failed_ids: Vec<String>,
+    pub downloads: Vec<DownloadResult>,
+    pub sources: BTreeMap<String, Vec<String>>,
+    /// Every file with its project, version, hashes and outcome.
+    pub entries: Vec<LogEntry>,
+    pub failures: Vec<LogFailure>,
+}
+
+impl ModrinthLog {
+    /// Projects a collection synced from this log should contain. These are the projects that
+    /// came from `collection`, or from any source when the log wasn't built from it. Projects
+    /// without a compatible version or whose download failed are included, files that modpacks
+    /// brought along are not. Logs without sources fall back to `ids` and `failed_ids`, leaving
+    /// out file names.
+    pub fn sync_ids(&self, collection: &str) -> Vec<String> {
+        let label = format!("collection:{collection}");
+        let from_collection: Vec<String> = self
+            .sources
+            .iter()
+            .filter(|(_, labels)| labels.contains(&label))
+            .map(|(id, _)| id.clone())
+            .collect();
+        if !from_collection.is_empty() {
+            return from_collection;
+        }
+        if !self.sources.is_empty() {
+            return self.sources.keys().cloned().collect();
+        }
+
+        let mut ids: Vec<String> = Vec::new();
+        for id in self.ids.iter().chain(&self.failed_ids) {
+            if is_project_id(id) && !ids.contains(id) {
+                ids.push(id.clone());
+            }
+        }
+        ids
+    }
+}
+
+/// Modrinth ids are 8 alphanumeric characters, modpack files not on Modrinth use their file name.
+fn is_project_id(id: &str) -> bool {
+    id.len() == 8 && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LogEntry {
+    pub id: String,
+    pub title: String,
+    pub slug: String,
+    pub version_id: String,
+    pub version_number: String,
+    pub file_name: String,
+    pub url: String,
+    pub size: u64,
+    pub sha1: String,
+    pub sha512: String,
+    /// Dependency edges of the version, as reported by Modrinth.
+    pub dependencies: Vec<Dependency>,
+    /// Labels of the sources the project came from, like `collection:<id>`.
+    pub sources: Vec<String>,
+    #[serde(flatten)]
+    pub outcome: DownloadOutcome,
+}
+
+/// A project that had no compatible version.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LogFailure {
+    pub id: String,
+    pub reason: String,
+    /// The full diagnosis, when it was asked for.
+    pub report: Option<FailureReport>,
+}
+
+/// A file the last run downloaded that isn't part of the pack anymore.
+pub struct StaleFile {
+    pub id: String,
+    pub path: PathBuf,
+    /// The project is still in the pack with a different file, so this is an old version of an
+    /// updated mod. Otherwise the project was unchecked or dropped from its source.
+    pub replaced: bool,
+}
+
+/// Files the last run downloaded into `path` that aren't part of `wanted` anymore. Only files
+/// listed in the previous `collection.json` are considered, anything the user added by hand is
+/// left alone.
+pub async fn stale_files(path: &Path, wanted: &[ModrinthProject]) -> Result<Vec<StaleFile>> {
+    // A broken log just means nothing is known to be stale
+    let Ok(Some(previous)) = read_log(path).await else {
+        return Ok(Vec::new());
+    };
+
+    let mut stale = Vec::new();
+    for project in previous.projects {
+        let file = path.join(&project.name);
+        if !project.name.is_empty()
+            && !wanted.iter().any(|w| w.name == project.name)
+            && fs::try_exists(&file).await?
+        {
+            stale.push(StaleFile {
+                replaced: wanted.iter().any(|w| w.id == project.id),
+                id: project.id,
+                path: file,
+            });
+        }
+    }
+
+    Ok(stale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn reads_logs_of_the_first_version() {
+        let log: ModrinthLog = serde_json::from_str(
+            r#"{
+                "ids": ["AANobbMI"],
+                "projects": [{
+                    "id": "AANobbMI",
+                    "name": "sodium-fabric-0.6.0.jar",
+                    "download_link": "https://cdn.modrinth.com/data/AANobbMI/versions/abc/sodium.jar"
+                }],
+                "failed_ids": ["NoVer123"]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(log.schema_version, 0);
+        assert_eq!(log.projects[0].name, "sodium-fabric-0.6.0.jar");
+        assert!(log.projects[0].sha512.is_empty());
+        assert_eq!(log.sync_ids("Coll1234"), ["AANobbMI", "NoVer123"]);
+    }
+
+    #[test]
+    fn syncs_the_projects_of_the_collection() {
+        let mut log = ModrinthLog {
+            ids: ids(&["AANNrr11", "MpFile00", "sodium-0.6.jar"]),
+            failed_ids: ids(&["NoVer123"]),
+            ..Default::default()
+        };
+        assert_eq!(
+            log.sync_ids("Coll1234"),
+            ["AANNrr11", "MpFile00", "NoVer123"]
+        );
+
+        log.sources = BTreeMap::from([
+            ("AANNrr11".to_string(), ids(&["collection:Coll1234"])),
+            ("NoVer123".to_string(), ids(&["collection:Coll1234"])),
+            ("Other123".to_string(), ids(&["project:other"])),
+        ]);
+        assert_eq!(log.sync_ids("Coll1234"), ["AANNrr11", "NoVer123"]);
+        assert_eq!(
+            log.sync_ids("Elsewhere"),
+            ["AANNrr11", "NoVer123", "Other123"]
+        );
+    }
 }
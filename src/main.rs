@@ -1,27 +1,275 @@
+#[macro_use]
+mod output;
+mod cache;
+mod checklist;
+mod compat;
+mod config;
 mod download;
+mod export;
+mod license;
 mod log;
 mod modrinth;
+mod mrpack;
 mod packwiz;
+mod plan;
+mod server;
+mod sources;
+mod store;
+mod throttle;
 
-use crate::download::download_files;
-use crate::log::create_log_file;
+use crate::cache::Cache;
+use crate::checklist::{apply_exclude, select_projects};
+use crate::compat::{LOADERS, compatibility_matrix, explain_failure};
+use crate::config::{Config, load_config};
+use crate::download::{download_files, print_summary};
+use crate::export::{ExportFormat, ModList};
+use crate::license::{LicensePolicy, LicenseReport};
+use crate::log::{
+    LogFailure, ModrinthLog, RunInfo, StaleFile, create_log_file, read_log, stale_files,
+};
 use crate::modrinth::{
-    check_modrinth_status, get_collection_details, get_mod_links, log_project_name,
+    ApiClient, check_modrinth_status, create_collection, get_mod_links, get_project, get_user,
+    refresh_collection_details, set_collection_projects,
+};
+use crate::mrpack::{install_overrides, remove_downloads, resolve_modpacks};
+use crate::packwiz::{
+    DependencyPolicy, PackInfo, create_pack, export_pack, init_packwiz, pack_version, refresh_pack,
 };
-use crate::packwiz::{create_pack, init_packwiz};
-use anyhow::{Context, Result};
+use crate::plan::{Plan, PlanFormat};
+use crate::server::{loader_version, setup_server};
+use crate::sources::{CollectionRef, Source, parse_collection_id, parse_sources, resolve_sources};
+use anyhow::{Context, Result, anyhow};
+use clap::{Parser, Subcommand};
 use colored::Colorize;
+use indicatif::HumanBytes;
 use inquire::validator::Validation;
 use inquire::{Select, Text};
 use regex::Regex;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::fs;
 
 pub const MODRINTH_URL: &str = "https://api.modrinth.com/";
 pub const MODRINTH_STAGING_URL: &str = "https://staging-api.modrinth.com/";
 
+/// Downloads every mod of one or more Modrinth collections. Runs interactively when no command is given.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Serve collections, versions and files from the prefetched store without network access
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Show what would be downloaded, skipped, deleted and run, without writing anything
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "table"
+    )]
+    dry_run: Option<PlanFormat>,
+
+    /// Print JSON events on stdout instead of text, and never prompt. Messages go to stderr
+    #[arg(long, global = true)]
+    json: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Download without prompting, the same steps the interactive mode walks through
+    Download {
+        /// Collection URLs/IDs and project URLs
+        #[arg(required = true)]
+        sources: Vec<String>,
+        #[arg(long, value_parser = ["fabric", "neoforge", "quilt", "forge"])]
+        loader: String,
+        /// Minecraft version, e.g. 1.21.1
+        #[arg(long)]
+        game_version: String,
+        /// Explain why projects without a compatible version failed
+        #[arg(long)]
+        explain: bool,
+        /// Set up a server next to the mods folder
+        #[arg(long)]
+        server: bool,
+        /// Accept the Minecraft EULA for the server
+        #[arg(long, requires = "server")]
+        accept_eula: bool,
+        /// Build a packwiz pack
+        #[arg(long)]
+        packwiz: bool,
+        /// What to do with required dependencies missing from the packwiz pack, overrides the
+        /// config
+        #[arg(long, value_enum, requires = "packwiz")]
+        dependencies: Option<DependencyPolicy>,
+        /// Delete the old jars of updated mods. Files of projects that left the pack are never
+        /// deleted
+        #[arg(long)]
+        remove_old: bool,
+    },
+    /// Fetch API responses and files into the local store for offline use
+    Prefetch {
+        /// Collection URLs/IDs and project URLs
+        #[arg(required = true)]
+        sources: Vec<String>,
+        #[arg(long, value_parser = ["fabric", "neoforge", "quilt", "forge"])]
+        loader: String,
+        /// Minecraft version, e.g. 1.21.1
+        #[arg(long)]
+        game_version: String,
+    },
+    /// Show how many projects support each Minecraft version per loader, and recommend the
+    /// newest version with the best coverage
+    Matrix {
+        /// Collection URLs/IDs and project URLs
+        #[arg(required = true)]
+        sources: Vec<String>,
+        /// Only check this loader
+        #[arg(long, value_parser = ["fabric", "neoforge", "quilt", "forge"])]
+        loader: Option<String>,
+        /// How many Minecraft versions to show, newest first
+        #[arg(long, default_value_t = 15)]
+        limit: usize,
+    },
+    /// Write the mod list of a downloaded pack as CSV, a Markdown table or an HTML credits page
+    Export {
+        /// The mods folder, or the collection.json in it
+        path: PathBuf,
+        #[arg(long, value_enum, default_value = "markdown")]
+        format: ExportFormat,
+        /// Where to write the list, defaults to modlist.csv, modlist.md or credits.html next to
+        /// collection.json, and pack.zip next to the mods folder
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// What to do with all rights reserved or unlicensed mods in a zip, overrides the config
+        #[arg(long, value_enum)]
+        license_policy: Option<LicensePolicy>,
+    },
+    /// List the licenses of every mod in a downloaded pack, and which ones may not be
+    /// redistributed
+    Licenses {
+        /// The mods folder, or the collection.json in it
+        path: PathBuf,
+    },
+    /// Work on a packwiz pack built by this tool
+    Packwiz {
+        #[command(subcommand)]
+        action: PackwizAction,
+    },
+    /// Create and edit Modrinth collections, needs a personal access token
+    Collection {
+        #[command(subcommand)]
+        action: CollectionAction,
+    },
+    /// Manage the shared download cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum CollectionAction {
+    /// Create a new collection, optionally filled with projects
+    Create {
+        name: String,
+        #[arg(long)]
+        description: Option<String>,
+        /// Project URLs, slugs or other sources to add
+        projects: Vec<String>,
+    },
+    /// Add projects to a collection
+    Add {
+        /// Collection URL or ID
+        collection: String,
+        #[arg(required = true)]
+        projects: Vec<String>,
+    },
+    /// Remove projects from a collection
+    Remove {
+        /// Collection URL or ID
+        collection: String,
+        #[arg(required = true)]
+        projects: Vec<String>,
+    },
+    /// Make a collection contain exactly the projects of a collection.json log, or of a text
+    /// file with one source per line
+    Sync {
+        /// Collection URL or ID
+        collection: String,
+        file: PathBuf,
+        /// Remove projects missing from the file without asking
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PackwizAction {
+    /// Rebuild index.toml after editing the pack by hand, like `packwiz refresh`
+    Refresh {
+        /// The pack directory, the one containing pack.toml
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Show where the cache lives and how much space it uses
+    Info,
+    /// Evict cached files that no known pack directory uses anymore
+    Gc,
+}
+
+/// Answers for the questions asked during a download run, `None` asks the user.
+struct RunOptions {
+    loader: String,
+    game_version: String,
+    sources: Vec<Source>,
+    /// Show the mod checklist, otherwise the exclude list is applied as is.
+    checklist: bool,
+    download: Option<bool>,
+    explain: Option<bool>,
+    server: Option<bool>,
+    accept_eula: Option<bool>,
+    packwiz: Option<bool>,
+    /// Delete the old jars of updated mods.
+    remove_old: Option<bool>,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    println!(
+    let cli = Cli::parse();
+    output::set_json(cli.json);
+
+    let result = run(cli).await;
+    if let Err(e) = &result {
+        output::event("error", &json!({ "message": format!("{e:#}") }));
+    }
+    result
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let mut config = load_config().await?;
+    config.offline |= cli.offline;
+
+    if let Some(command) = cli.command {
+        return run_command(command, &config, cli.dry_run).await;
+    }
+
+    anyhow::ensure!(
+        !output::is_json(),
+        "JSON output can't prompt, use the download command with its flags instead"
+    );
+
+    say!(
         "{}",
         r#"+------------------------------------------------------------------------------------+
 |                          Modrinth API Collection Downloader                        |
@@ -48,7 +296,7 @@ async fn main() -> Result<()> {
         "Neoforge" => "neoforge".to_string(),
         "Quilt" => "quilt".to_string(),
         "Forge" => {
-            println!(
+            say!(
                 "{}",
                 "Warning: It's recommended to use NeoForge in Minecraft 1.20+".bright_red()
             );
@@ -80,100 +328,855 @@ async fn main() -> Result<()> {
         .map_err(|e| anyhow::anyhow!("Version prompt failed: {e}"))?;
 
     // Collection URL prompt (no unwrap)
-    let collection_url: String =
-        Text::new("What is the URL (or ID) of the collection you are trying to download?")
-            .with_default("XXXXXX")
-            .prompt()
-            .map_err(|e| anyhow::anyhow!("Collection prompt failed: {e}"))?;
+    let sources_input: String = Text::new(
+        "What are the URLs (or IDs) of the collections you are trying to download?\nSeparate multiple collections or project URLs with commas or spaces.",
+    )
+    .with_default("XXXXXX")
+    .prompt()
+    .map_err(|e| anyhow::anyhow!("Collection prompt failed: {e}"))?;
+    let sources = parse_sources(&sources_input)?;
+
+    run_download(
+        RunOptions {
+            loader: mod_platform,
+            game_version: minecraft_version,
+            sources,
+            checklist: true,
+            download: None,
+            explain: None,
+            server: None,
+            accept_eula: None,
+            packwiz: None,
+            remove_old: None,
+        },
+        &config,
+        cli.dry_run,
+    )
+    .await
+}
+
+/// Asks a yes/no question, unless the answer was given up front.
+fn ask(question: &str, answer: Option<bool>) -> Result<bool> {
+    if let Some(answer) = answer {
+        return Ok(answer);
+    }
+
+    let ans: &str = Select::new(question, vec!["Yes", "No"])
+        .prompt()
+        .map_err(|e| anyhow::anyhow!("Selection failed: {e}"))?;
+    Ok(ans == "Yes")
+}
+
+/// Resolves the sources and runs the download, server and packwiz steps.
+async fn run_download(
+    options: RunOptions,
+    config: &Config,
+    dry_run: Option<PlanFormat>,
+) -> Result<()> {
+    let RunOptions {
+        loader: mod_platform,
+        game_version: minecraft_version,
+        sources,
+        ..
+    } = &options;
 
     // Check / create directory
-    let dir = get_path().await?;
+    let dir = match dry_run {
+        Some(_) => mods_path()?.to_str().unwrap().to_string(),
+        None => get_path().await?,
+    };
+    let cache = Cache::open(&config.cache)?.map(Arc::new);
+    let api = ApiClient::new(config).await?.read_only(dry_run.is_some());
 
     // Web functions
-    check_modrinth_status()
-        .await
-        .context("Modrinth status check failed: ")?;
+    if !config.offline {
+        check_modrinth_status()
+            .await
+            .context("Modrinth status check failed: ")?;
+    }
 
-    let collection = get_collection_details(collection_url)
+    let resolved = resolve_sources(&api, sources)
         .await
         .context("Getting collection details failed: ")?;
-    println!(
+    say!(
         "{}",
-        format!("Got {} projects...", collection.projects.len()).bright_green()
+        format!(
+            "Got {} projects from {} sources...",
+            resolved.project_ids.len(),
+            sources.len()
+        )
+        .bright_green()
     );
+    output::event(
+        "sources",
+        &json!({ "projects": resolved.all_ids().len(), "provenance": resolved.provenance }),
+    );
+
+    let (mut links, mut failed) = get_mod_links(
+        &api,
+        resolved.project_ids,
+        mod_platform.clone(),
+        minecraft_version.clone(),
+    )
+    .await?;
+    links.extend(resolved.pinned);
 
-    let (links, failed) =
-        get_mod_links(collection.projects, mod_platform, minecraft_version).await?;
+    // Modpacks contribute their own files instead of themselves
+    let (modpacks, failed_packs) = resolve_modpacks(
+        &api,
+        resolved.modpacks,
+        mod_platform.clone(),
+        minecraft_version.clone(),
+        config,
+        cache.clone(),
+        dry_run.is_some(),
+    )
+    .await?;
+    failed.extend(failed_packs);
+    for pack in modpacks.iter().filter(|pack| pack.mrpack.is_some()) {
+        say!(
+            "{}",
+            format!("Modpack {} adds {} mods...", pack.name, pack.mods.len()).bright_green()
+        );
+        links.extend(pack.mods.iter().cloned());
+    }
+
+    let links = if options.checklist {
+        select_projects(&api, links, config, dry_run.is_none()).await?
+    } else {
+        apply_exclude(&api, links, config).await?
+    };
+    output::event("versions", &json!({ "projects": links, "failed": failed }));
+
+    if let Some(format) = dry_run {
+        let dir = PathBuf::from(&dir);
+        let mut plan = Plan::new(
+            mod_platform,
+            minecraft_version,
+            &links,
+            &modpacks,
+            &dir,
+            cache.as_deref(),
+        )
+        .await?;
+        // Steps turned off with a flag are left out, unanswered ones could still happen
+        if options.packwiz != Some(false) {
+            let info = pack_info(
+                &api,
+                &dir,
+                &resolved.collections,
+                mod_platform,
+                minecraft_version,
+            )
+            .await?;
+            plan = plan
+                .with_packwiz(&api, &dir, &info, &links, config.packwiz.dependencies)
+                .await?;
+        }
+        if options.server != Some(false) {
+            plan.server = Some(format!(
+                "{mod_platform} server for Minecraft {minecraft_version}"
+            ));
+        }
+        plan.failed = failed;
+        return plan.print(format);
+    }
 
     // Downloader
     {
-        let selection_options = vec!["Yes", "No"];
-        let download_ans: &str = Select::new(
+        let mut downloaded = None;
+        if ask(
             &format!("Would you like to download {} mods?", links.len()),
-            selection_options,
-        )
-        .prompt()
-        .map_err(|e| anyhow::anyhow!("Download selection failed: {e}"))?;
+            options.download,
+        )? {
+            let mut results =
+                download_files(links.clone(), dir.clone(), config, cache.clone()).await?;
 
-        match download_ans {
-            "Yes" => {
-                download_files(links.clone(), dir.clone()).await?;
-                create_log_file(links.clone(), failed.clone(), dir.parse()?).await?;
+            // Configs, resource packs and the like go next to the mods folder
+            let root = PathBuf::from(&dir)
+                .parent()
+                .map(|p| p.to_path_buf())
+                .unwrap_or_default();
+            for pack in &modpacks {
+                for (subdir, files) in &pack.other {
+                    results.extend(
+                        download_files(files.clone(), root.join(subdir), config, cache.clone())
+                            .await?,
+                    );
+                }
             }
-            _ => {}
-        };
+            print_summary(&results);
+
+            // Old versions of updated mods can go, kept when the new version failed to download.
+            // Files of projects that left the pack are only reported.
+            let (replaced, unused): (Vec<StaleFile>, Vec<StaleFile>) =
+                stale_files(Path::new(&dir), &links)
+                    .await?
+                    .into_iter()
+                    .filter(|f| {
+                        !results
+                            .iter()
+                            .any(|r| r.project.id == f.id && !r.outcome.is_success())
+                    })
+                    .partition(|f| f.replaced);
+            for file in &unused {
+                say!(
+                    "{} is no longer part of the pack, it was left in place",
+                    file.path.display()
+                );
+                output::event("unused", &json!({ "path": file.path }));
+            }
+            if !replaced.is_empty()
+                && ask(
+                    &format!(
+                        "Would you like to remove {} old versions of updated mods?",
+                        replaced.len()
+                    ),
+                    options.remove_old,
+                )?
+            {
+                for file in &replaced {
+                    fs::remove_file(&file.path).await?;
+                    say!("Removed {}", file.path.display());
+                    output::event("removed", &json!({ "path": file.path }));
+                }
+            }
+
+            for (pack, mrpack) in modpacks
+                .iter()
+                .filter_map(|pack| Some((pack, pack.mrpack.as_ref()?)))
+            {
+                let written = install_overrides(mrpack, &root).await?;
+                say!(
+                    "{}",
+                    format!("Installed {written} override files from {}", pack.name).bright_green()
+                );
+                output::event(
+                    "overrides",
+                    &json!({ "modpack": pack.name, "files": written }),
+                );
+            }
+            downloaded = Some(results);
+        }
+        remove_downloads().await?;
 
         // Failed file logging
-        if failed.len() > 0 {
-            let selection_options = vec!["Yes", "No"];
-            let failed_ans: &str = Select::new(
+        let mut reports = HashMap::new();
+        if !failed.is_empty()
+            && ask(
                 &format!(
-                    "Failed to get versions for {} files, would you like to see their names and relevant links?",
+                    "Failed to get versions for {} files, would you like to see why and what would work instead?",
                     failed.len()
                 ),
-                selection_options,
-            )
-                .prompt()
-                .map_err(|e| anyhow::anyhow!("Selection failed: {e}"))?;
+                options.explain,
+            )?
+        {
+            for failed_file in &failed {
+                // One lookup failing shouldn't cost the other reports or the log
+                let report =
+                    match explain_failure(&api, failed_file, mod_platform, minecraft_version).await
+                    {
+                        Ok(report) => report,
+                        Err(e) => {
+                            say!(
+                                "{}",
+                                format!("Could not explain why {failed_file} failed: {e:#}")
+                                    .bright_red()
+                            );
+                            continue;
+                        }
+                    };
+                report.print(mod_platform);
+                output::event("failure", &report);
+                reports.insert(failed_file.clone(), report);
+            }
+        }
 
-            match failed_ans {
-                "Yes" => {
-                    for failed_file in failed {
-                        log_project_name(failed_file).await?;
+        if let Some(results) = downloaded {
+            let failures = failed
+                .iter()
+                .map(|id| {
+                    let report = reports.remove(id);
+                    LogFailure {
+                        id: id.clone(),
+                        reason: report.as_ref().map_or_else(
+                            || {
+                                format!(
+                                    "No {mod_platform} version for Minecraft {minecraft_version}"
+                                )
+                            },
+                            |r| r.verdict.clone(),
+                        ),
+                        report,
                     }
-                }
-                _ => {}
+                })
+                .collect();
+            let info = RunInfo {
+                loader: mod_platform.to_string(),
+                game_version: minecraft_version.to_string(),
+                collections: resolved.collections.clone(),
+                sources: resolved.provenance.clone(),
+                failures,
             };
+            create_log_file(&api, results, info, dir.parse()?).await?;
         }
     }
 
+    // Server setup
+    if ask(
+        &format!(
+            "Would you like to set up a {} server for Minecraft {} next to the mods folder?",
+            mod_platform, minecraft_version
+        ),
+        options.server,
+    )? {
+        let accept_eula = ask(
+            "Do you accept the Minecraft EULA (https://aka.ms/MinecraftEULA)? Answering yes writes eula.txt for you.",
+            options.accept_eula,
+        )?;
+
+        setup_server(dir.parse()?, mod_platform, minecraft_version, accept_eula).await?;
+        say!(
+            "{}",
+            "Server is ready, run start.sh or start.bat to launch it.".bright_green()
+        );
+        output::event(
+            "server",
+            &json!({ "loader": mod_platform, "game_version": minecraft_version, "eula": accept_eula }),
+        );
+    }
+
     // Packwiz addon
-    {
-        let selection_options = vec!["Yes", "No"];
-        let platform_ans: &str = Select::new(
-            "Would you like to build a packwiz pack? This requires packwiz to either be installed in your path, or we will download it for you.",
-            selection_options,
+    if ask(
+        "Would you like to build a packwiz pack? Exporting it as a .mrpack uses packwiz from your path, or we will download it for you.",
+        options.packwiz,
+    )? {
+        let info = pack_info(
+            &api,
+            Path::new(&dir),
+            &resolved.collections,
+            mod_platform,
+            minecraft_version,
         )
-            .prompt()
-            .map_err(|e| anyhow::anyhow!("Platform selection failed: {e}"))?;
+        .await?;
+        let report = create_pack(
+            &api,
+            dir.parse()?,
+            &info,
+            &links,
+            config.packwiz.dependencies,
+        )
+        .await?;
+        report.print();
+        match init_packwiz(dir.parse()?).await? {
+            Some(exe) => export_pack(&exe, &report.dir).await?,
+            None => say!(
+                "No packwiz download for this platform, install packwiz and run `packwiz mr export` in {} to get a .mrpack",
+                report.dir.display()
+            ),
+        }
+        output::event("export", &json!({ "format": "packwiz", "report": report }));
+    }
+
+    Ok(())
+}
+
+async fn run_command(command: Command, config: &Config, dry_run: Option<PlanFormat>) -> Result<()> {
+    match command {
+        Command::Download {
+            sources,
+            loader,
+            game_version,
+            explain,
+            server,
+            accept_eula,
+            packwiz,
+            dependencies,
+            remove_old,
+        } => {
+            let mut config = config.clone();
+            if let Some(dependencies) = dependencies {
+                config.packwiz.dependencies = dependencies;
+            }
+
+            let sources = parse_sources(&sources.join(" "))?;
+            return run_download(
+                RunOptions {
+                    loader,
+                    game_version,
+                    sources,
+                    checklist: false,
+                    download: Some(true),
+                    explain: Some(explain),
+                    server: Some(server),
+                    accept_eula: Some(accept_eula),
+                    packwiz: Some(packwiz),
+                    remove_old: Some(remove_old),
+                },
+                &config,
+                dry_run,
+            )
+            .await;
+        }
+        Command::Prefetch {
+            sources,
+            loader,
+            game_version,
+        } => {
+            anyhow::ensure!(!config.offline, "Prefetching needs network access");
+
+            let cache = Arc::new(
+                Cache::open(&config.cache)?
+                    .ok_or_else(|| anyhow!("Prefetching needs the cache to be enabled"))?,
+            );
+            let api = ApiClient::new(config).await?.read_only(dry_run.is_some());
+
+            check_modrinth_status()
+                .await
+                .context("Modrinth status check failed: ")?;
+
+            let sources = parse_sources(&sources.join(" "))?;
+            let resolved = resolve_sources(&api, &sources)
+                .await
+                .context("Getting collection details failed: ")?;
+            for project in resolved.provenance.keys() {
+                get_project(&api, project).await?;
+            }
+
+            let (mut links, mut failed) = get_mod_links(
+                &api,
+                resolved.project_ids,
+                loader.clone(),
+                game_version.clone(),
+            )
+            .await?;
+            links.extend(resolved.pinned);
+
+            let (modpacks, failed_packs) = resolve_modpacks(
+                &api,
+                resolved.modpacks,
+                loader.clone(),
+                game_version.clone(),
+                config,
+                Some(cache.clone()),
+                dry_run.is_some(),
+            )
+            .await?;
+            failed.extend(failed_packs);
+            for pack in &modpacks {
+                links.extend(pack.mods.iter().cloned());
+                links.extend(pack.other.values().flatten().cloned());
+            }
+            // The cache keeps the .mrpack files, the overrides aren't needed here
+            remove_downloads().await?;
+
+            // Files are kept in a staging directory, so gc treats them as in use
+            let staging = cache.root().join("prefetch");
+            if let Some(format) = dry_run {
+                let mut plan = Plan::new(
+                    &loader,
+                    &game_version,
+                    &links,
+                    &modpacks,
+                    &staging,
+                    Some(&cache),
+                )
+                .await?;
+                plan.failed = failed;
+                return plan.print(format);
+            }
+            let results = download_files(links, staging, config, Some(cache)).await?;
+            print_summary(&results);
+
+            if !failed.is_empty() {
+                say!(
+                    "{}",
+                    format!("{} projects have no compatible version", failed.len()).bright_red()
+                );
+                output::event("failed", &json!({ "ids": failed }));
+            }
+        }
+        Command::Matrix {
+            sources,
+            loader,
+            limit,
+        } => {
+            let api = ApiClient::new(config).await?;
+
+            let sources = parse_sources(&sources.join(" "))?;
+            let resolved = resolve_sources(&api, &sources)
+                .await
+                .context("Getting collection details failed: ")?;
+            let ids = resolved.all_ids();
+            say!(
+                "{}",
+                format!("Checking {} projects...", ids.len()).bright_green()
+            );
+
+            let loaders = match &loader {
+                Some(loader) => vec![loader.as_str()],
+                None => LOADERS.to_vec(),
+            };
+            let matrix = compatibility_matrix(&api, &ids, &loaders).await?;
+            matrix.print(limit);
+            output::event("matrix", &matrix);
+            for recommendation in matrix.recommendations() {
+                output::event("recommendation", &recommendation);
+            }
+        }
+        Command::Export {
+            path,
+            format,
+            output,
+            license_policy,
+        } => {
+            let (dir, log) = pack_log(path).await?;
+            let root = dir.parent().map(Path::to_path_buf).unwrap_or_default();
+            let file = output.unwrap_or_else(|| {
+                let base = if format.bundles_files() { &root } else { &dir };
+                base.join(format.default_file_name())
+            });
+
+            if dry_run.is_some() {
+                say!(
+                    "Would write {} mods to {}",
+                    log.entries.len(),
+                    file.display()
+                );
+                return Ok(());
+            }
+
+            let api = ApiClient::new(config).await?;
+            let list = ModList::from_log(&api, &log).await?;
+            if format.bundles_files() {
+                LicenseReport::of(&list).check(license_policy.unwrap_or(config.license_policy))?;
+            }
+            list.write(&log, format, &root, &file).await?;
+            say!(
+                "{}",
+                format!("Wrote {} mods to {}", list.mods.len(), file.display()).bright_green()
+            );
+            output::event("modlist", &json!({ "path": file, "mods": list.mods }));
+        }
+        Command::Licenses { path } => {
+            let (_, log) = pack_log(path).await?;
+            let api = ApiClient::new(config).await?;
+            let list = ModList::from_log(&api, &log).await?;
+            let report = LicenseReport::of(&list);
+            report.print();
+            output::event("licenses", &report);
+        }
+        Command::Packwiz { action } => match action {
+            PackwizAction::Refresh { dir } => {
+                if dry_run.is_some() {
+                    say!("Would rebuild the index of {}", dir.display());
+                    return Ok(());
+                }
+                let indexed = refresh_pack(&dir).await?;
+                say!(
+                    "{}",
+                    format!("Indexed {indexed} files in {}", dir.display()).bright_green()
+                );
+                output::event("refresh", &json!({ "dir": dir, "files": indexed }));
+            }
+        },
+        Command::Collection { action } => {
+            let api = ApiClient::new(config).await?.read_only(dry_run.is_some());
+            run_collection_command(action, &api, dry_run.is_some()).await?;
+        }
+        Command::Cache { action } => {
+            let cache =
+                Cache::open(&config.cache)?.ok_or_else(|| anyhow!("The cache is disabled"))?;
 
-        match platform_ans {
-            "Yes" => {
-                init_packwiz(dir.clone().parse()?).await?;
-                create_pack(dir.parse()?, links.clone()).await?;
+            match action {
+                CacheAction::Info => {
+                    let (count, bytes) = cache.size().await?;
+                    say!("Cache directory: {}", cache.root().display());
+                    say!("{} files, {}", count, HumanBytes(bytes));
+                    output::event(
+                        "cache",
+                        &json!({ "dir": cache.root(), "files": count, "bytes": bytes }),
+                    );
+                }
+                CacheAction::Gc => {
+                    let (evicted, freed) = cache.gc(dry_run.is_some()).await?;
+                    let verb = if dry_run.is_some() {
+                        "Would evict"
+                    } else {
+                        "Evicted"
+                    };
+                    say!(
+                        "{}",
+                        format!("{verb} {} files, freeing {}", evicted, HumanBytes(freed))
+                            .bright_green()
+                    );
+                    output::event(
+                        "gc",
+                        &json!({ "evicted": evicted, "freed": freed, "dry_run": dry_run.is_some() }),
+                    );
+                }
             }
-            _ => {}
-        };
+        }
     }
 
     Ok(())
 }
 
-pub(crate) async fn get_path() -> Result<String> {
+/// Edits collections. A dry run only prints the changes.
+async fn run_collection_command(
+    action: CollectionAction,
+    api: &ApiClient,
+    dry_run: bool,
+) -> Result<()> {
+    let resolve = async |inputs: &[String]| -> Result<Vec<String>> {
+        let sources = parse_sources(&inputs.join(" "))?;
+        Ok(resolve_sources(api, &sources).await?.all_ids())
+    };
+
+    // Only a sync removes projects the user didn't name, so only a sync asks first
+    let confirm_removal = match &action {
+        CollectionAction::Sync { yes, .. } => yes.then_some(true),
+        _ => Some(true),
+    };
+
+    let (collection_id, current, desired) = match action {
+        CollectionAction::Create {
+            name,
+            description,
+            projects,
+        } => {
+            let projects = if projects.is_empty() {
+                Vec::new()
+            } else {
+                resolve(&projects).await?
+            };
+
+            if dry_run {
+                say!(
+                    "Would create collection {name} with {} projects",
+                    projects.len()
+                );
+                output::event(
+                    "collection",
+                    &json!({ "name": name, "projects": projects, "dry_run": true }),
+                );
+                return Ok(());
+            }
+
+            let collection =
+                create_collection(api, &name, description.as_deref(), projects).await?;
+            say!(
+                "{}",
+                format!(
+                    "Created collection {} with {} projects: https://modrinth.com/collection/{}",
+                    collection.name,
+                    collection.projects.len(),
+                    collection.id
+                )
+                .bright_green()
+            );
+            output::event(
+                "collection",
+                &json!({ "id": collection.id, "name": collection.name, "projects": collection.projects }),
+            );
+            return Ok(());
+        }
+        CollectionAction::Add {
+            collection,
+            projects,
+        } => {
+            let id = parse_collection_id(&collection)?;
+            let current = refresh_collection_details(api, &id).await?.projects;
+            let mut desired = current.clone();
+            for project in resolve(&projects).await? {
+                if !desired.contains(&project) {
+                    desired.push(project);
+                }
+            }
+            (id, current, desired)
+        }
+        CollectionAction::Remove {
+            collection,
+            projects,
+        } => {
+            let id = parse_collection_id(&collection)?;
+            let remove = resolve(&projects).await?;
+            let current = refresh_collection_details(api, &id).await?.projects;
+            let mut desired = current.clone();
+            desired.retain(|p| !remove.contains(p));
+            (id, current, desired)
+        }
+        CollectionAction::Sync {
+            collection, file, ..
+        } => {
+            let id = parse_collection_id(&collection)?;
+            let contents = fs::read_to_string(&file).await?;
+
+            let desired = if file.extension().is_some_and(|ext| ext == "json") {
+                let log: ModrinthLog = serde_json::from_str(&contents)
+                    .with_context(|| format!("parsing {}", file.display()))?;
+                log.sync_ids(&id)
+            } else {
+                let lines: Vec<String> = contents
+                    .lines()
+                    .map(|l| l.trim().to_string())
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .collect();
+                resolve(&lines).await?
+            };
+            let current = refresh_collection_details(api, &id).await?.projects;
+            (id, current, desired)
+        }
+    };
+
+    let current: HashSet<String> = current.into_iter().collect();
+    let wanted: HashSet<String> = desired.iter().cloned().collect();
+    let added: Vec<&String> = wanted.difference(&current).collect();
+    let removed: Vec<&String> = current.difference(&wanted).collect();
+    let event = json!({
+        "id": collection_id,
+        "added": added,
+        "removed": removed,
+        "dry_run": dry_run,
+    });
+
+    if added.is_empty() && removed.is_empty() {
+        say!("Collection {collection_id} is already up to date.");
+        output::event("collection", &event);
+        return Ok(());
+    }
+
+    if dry_run {
+        for id in &added {
+            say!("add {id}");
+        }
+        for id in &removed {
+            say!("remove {id}");
+        }
+        say!(
+            "Would update collection {collection_id}: {} added, {} removed",
+            added.len(),
+            removed.len()
+        );
+        output::event("collection", &event);
+        return Ok(());
+    }
+
+    if !removed.is_empty() {
+        for id in &removed {
+            say!("remove {id}");
+        }
+        anyhow::ensure!(
+            confirm_removal.is_some() || !output::is_json(),
+            "Not removing {} projects from collection {collection_id} without --yes in JSON mode",
+            removed.len()
+        );
+        if !ask(
+            &format!(
+                "Would you like to remove {} projects from collection {collection_id}?",
+                removed.len()
+            ),
+            confirm_removal,
+        )? {
+            say!("Collection {collection_id} was left unchanged.");
+            return Ok(());
+        }
+    }
+
+    set_collection_projects(api, &collection_id, desired).await?;
+    say!(
+        "{}",
+        format!(
+            "Updated collection {collection_id}: {} added, {} removed",
+            added.len(),
+            removed.len()
+        )
+        .bright_green()
+    );
+    output::event("collection", &event);
+
+    Ok(())
+}
+
+/// pack.toml details for a pack built from `collections`: their names, their owners as authors and
+/// the newest loader build for the Minecraft version. The pack version of an earlier build is
+/// kept. Authors and the loader version are left out when they can't be looked up.
+async fn pack_info(
+    api: &ApiClient,
+    dir: &Path,
+    collections: &[CollectionRef],
+    loader: &str,
+    game_version: &str,
+) -> Result<PackInfo> {
+    let names: Vec<&str> = collections.iter().map(|c| c.name.as_str()).collect();
+
+    let mut authors: Vec<String> = Vec::new();
+    for collection in collections {
+        if let Ok(user) = get_user(api, &collection.user).await
+            && !authors.contains(&user.username)
+        {
+            authors.push(user.username);
+        }
+    }
+
+    let loader_version = match loader_version(loader, game_version).await {
+        Ok(version) => Some(version),
+        Err(e) => {
+            say!(
+                "{}",
+                format!("Could not look up the {loader} version, pack.toml won't pin it: {e:#}")
+                    .bright_red()
+            );
+            None
+        }
+    };
+
+    Ok(PackInfo {
+        name: if names.is_empty() {
+            "Modpack".to_string()
+        } else {
+            names.join(" + ")
+        },
+        author: authors.join(", "),
+        version: pack_version(dir)
+            .await?
+            .unwrap_or_else(|| "1.0.0".to_string()),
+        minecraft: game_version.to_string(),
+        loader: loader.to_string(),
+        loader_version,
+    })
+}
+
+/// Reads the collection.json of a downloaded pack, `path` is the mods folder or the log itself.
+/// Returns the mods folder with the log.
+async fn pack_log(path: PathBuf) -> Result<(PathBuf, ModrinthLog)> {
+    let dir = if path.is_file() {
+        path.parent().map(Path::to_path_buf).unwrap_or_default()
+    } else {
+        path
+    };
+    let log = read_log(&dir).await?.ok_or_else(|| {
+        anyhow!(
+            "No collection.json in {}, download the pack first",
+            dir.display()
+        )
+    })?;
+
+    Ok((dir, log))
+}
+
+/// The mods directory next to the executable.
+fn mods_path() -> Result<PathBuf> {
     let mut exe_path = std::env::current_exe()?;
     exe_path.pop();
     exe_path.push("mods");
 
+    Ok(exe_path)
+}
+
+pub(crate) async fn get_path() -> Result<String> {
+    let exe_path = mods_path()?;
+
     if !fs::try_exists(&exe_path).await? {
         fs::create_dir(&exe_path).await?;
     }
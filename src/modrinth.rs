@@ -1,23 +1,246 @@
+use crate::cache::Cache;
+use crate::config::{Config, TOKEN_ENV, credentials_path, resolve_token};
+use crate::store::{ResponseStore, StoredResponse};
 use crate::{MODRINTH_STAGING_URL, MODRINTH_URL};
 use anyhow::{Result, anyhow};
 use colored::Colorize;
-use reqwest::Client;
-use reqwest::header::USER_AGENT;
+use reqwest::header::{
+    AUTHORIZATION, ETAG, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, USER_AGENT,
+};
+use reqwest::{Client, Method, StatusCode};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Client used for every Modrinth API call. Successful responses are written to the response
+/// store and reused until their TTL runs out, after which they are revalidated with a conditional
+/// request. In offline mode they are served from the store instead of the network. A configured
+/// personal access token is sent with every request, and responses fetched with it are stored
+/// apart from anonymous ones so they never show up in a run without that token. A read only
+/// client uses the store but never writes to it.
+pub struct ApiClient {
+    client: Client,
+    store: Option<ResponseStore>,
+    offline: bool,
+    ttl_secs: u64,
+    token: Option<HeaderValue>,
+    /// Short hash of the token, added to the store key of every response fetched with it.
+    token_fingerprint: Option<String>,
+    read_only: bool,
+    /// URLs invalidated by a read only client, their stored responses are ignored instead.
+    ignored: Mutex<HashSet<String>>,
+}
+
+/// Status and body of an API response.
+pub struct ApiResponse {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl ApiClient {
+    pub async fn new(config: &Config) -> Result<Self> {
+        let store = Cache::open(&config.cache)?.map(|c| ResponseStore::new(c.root().join("api")));
+        anyhow::ensure!(
+            !config.offline || store.is_some(),
+            "Offline mode needs the cache to be enabled"
+        );
+
+        let token = resolve_token(config).await?;
+        let token_fingerprint = token
+            .as_ref()
+            .map(|token| format!("{:x}", Sha256::digest(token.as_bytes()))[..16].to_string());
+        let token = match token {
+            Some(token) => {
+                let mut value = HeaderValue::from_str(&token)
+                    .map_err(|_| anyhow!("The Modrinth token contains invalid characters"))?;
+                // keeps the token out of debug output
+                value.set_sensitive(true);
+                Some(value)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            client: Client::new(),
+            store,
+            offline: config.offline,
+            ttl_secs: config.api.cache_ttl_secs,
+            token,
+            token_fingerprint,
+            read_only: false,
+            ignored: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Leaves the store untouched, used by dry runs.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Sends an authenticated request with a JSON body, used for changes like editing
+    /// collections. Nothing is cached, and the stored response for `url` is dropped since it is
+    /// outdated now.
+    pub async fn send_json(
+        &self,
+        method: Method,
+        url: &str,
+        body: &serde_json::Value,
+    ) -> Result<ApiResponse> {
+        anyhow::ensure!(!self.offline, "Changing collections needs network access");
+        let token = self.token.as_ref().ok_or_else(|| {
+            anyhow!(missing_token_message(
+                "Changing collections needs authentication"
+            ))
+        })?;
+
+        let resp = self
+            .client
+            .request(method, url)
+            .header(
+                USER_AGENT,
+                "kay-xr/modrinth_collection_downloader/0.1.0 (archangel@angelware.net)",
+            )
+            .header(AUTHORIZATION, token.clone())
+            .json(body)
+            .send()
+            .await?;
+
+        self.invalidate(url).await?;
+
+        let status = resp.status();
+        let body = resp.text().await?;
+
+        Ok(ApiResponse { status, body })
+    }
+
+    /// Drops the stored responses for `url`, so the next `get` asks Modrinth again.
+    pub async fn invalidate(&self, url: &str) -> Result<()> {
+        match &self.store {
+            Some(_) if !self.offline && self.read_only => {
+                self.ignored.lock().unwrap().insert(url.to_string());
+                Ok(())
+            }
+            Some(store) if !self.offline => {
+                store.remove(url).await?;
+                store.remove(&self.store_key(url)).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Key of the stored response for `url`, tied to the token when there is one.
+    fn store_key(&self, url: &str) -> String {
+        match &self.token_fingerprint {
+            Some(fingerprint) => format!("{url}#token={fingerprint}"),
+            None => url.to_string(),
+        }
+    }
+
+    pub async fn get(&self, url: &str) -> Result<ApiResponse> {
+        let key = self.store_key(url);
+        let stored = match &self.store {
+            Some(_) if self.ignored.lock().unwrap().contains(url) => None,
+            Some(store) => store.load(&key).await?,
+            None => None,
+        };
+
+        if self.offline {
+            return stored
+                .map(|stored| ApiResponse {
+                    status: StatusCode::OK,
+                    body: stored.body,
+                })
+                .ok_or_else(|| {
+                    anyhow!("{url} was not prefetched, run the prefetch command while online first")
+                });
+        }
+
+        if let Some(stored) = &stored
+            && stored.is_fresh(self.ttl_secs)
+        {
+            return Ok(ApiResponse {
+                status: StatusCode::OK,
+                body: stored.body.clone(),
+            });
+        }
+
+        let mut req = self.client.get(url).header(
+            USER_AGENT,
+            "kay-xr/modrinth_collection_downloader/0.1.0 (archangel@angelware.net)",
+        );
+        if let Some(token) = &self.token {
+            req = req.header(AUTHORIZATION, token.clone());
+        }
+        if let Some(stored) = &stored {
+            if let Some(etag) = &stored.etag {
+                req = req.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &stored.last_modified {
+                req = req.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let resp = req.send().await?;
+
+        // Still valid, keep the stored body and restart its TTL
+        if resp.status() == StatusCode::NOT_MODIFIED
+            && let (Some(store), Some(mut stored)) = (&self.store, stored)
+        {
+            if !self.read_only {
+                stored.touch();
+                store.save(&stored).await?;
+            }
+            return Ok(ApiResponse {
+                status: StatusCode::OK,
+                body: stored.body,
+            });
+        }
+
+        let status = resp.status();
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let body = resp.text().await?;
+
+        if status.is_success()
+            && !self.read_only
+            && let Some(store) = &self.store
+        {
+            store
+                .save(&StoredResponse::new(
+                    &key,
+                    body.clone(),
+                    etag,
+                    last_modified,
+                ))
+                .await?;
+        }
+
+        Ok(ApiResponse { status, body })
+    }
+}
 
 /// Check the status of the Modrinth API
 pub async fn check_modrinth_status() -> Result<()> {
-    let use_url: &str;
-
-    if cfg!(debug_assertions) {
-        println!(
+    let use_url = if cfg!(debug_assertions) {
+        say!(
             "{}",
             "Running in debug mode, using staging URL".bright_green()
         );
-        use_url = MODRINTH_STAGING_URL;
+        MODRINTH_STAGING_URL
     } else {
-        use_url = MODRINTH_URL;
-    }
+        MODRINTH_URL
+    };
 
     let res = reqwest::get(use_url).await?;
 
@@ -32,31 +255,110 @@ pub async fn check_modrinth_status() -> Result<()> {
 }
 
 /// Get the details of a collection from modrinth.
-pub async fn get_collection_details(url: String) -> Result<Collection> {
-    let collection_id = extract_collection_id(url)?;
+pub async fn get_collection_details(api: &ApiClient, collection_id: &str) -> Result<Collection> {
+    match find_collection(api, collection_id).await? {
+        Some(collection) => Ok(collection),
+        None if !api.has_token() => Err(anyhow!(missing_token_message(&format!(
+            "Collection {collection_id} was not found"
+        )))),
+        None => Err(anyhow!(format!(
+            "Failed with status: {}",
+            StatusCode::NOT_FOUND
+        ))),
+    }
+}
 
+/// Like [`get_collection_details`], but a collection that doesn't exist (or isn't visible) is
+/// `None` instead of an error.
+pub async fn find_collection(api: &ApiClient, collection_id: &str) -> Result<Option<Collection>> {
     let url = format!("https://api.modrinth.com/v3/collection/{}", collection_id);
 
-    let client = Client::new();
-    let resp = client
-        .get(&url)
-        .header(
-            USER_AGENT,
-            "kay-xr/modrinth_collection_downloader/0.1.0 (archangel@angelware.net)",
+    let resp = api.get(&url).await?;
+
+    if resp.status.is_success() {
+        let collection: Collection = serde_json::from_str(&resp.body)?;
+        Ok(Some(collection))
+    } else if resp.status == StatusCode::NOT_FOUND {
+        Ok(None)
+    } else if !api.has_token() && resp.status == StatusCode::UNAUTHORIZED {
+        Err(anyhow!(missing_token_message(&format!(
+            "Collection {collection_id} was not found"
+        ))))
+    } else {
+        Err(anyhow!(format!("Failed with status: {}", resp.status)))
+    }
+}
+
+/// Like [`get_collection_details`], but never uses a stored response. Used before editing a
+/// collection, so changes made elsewhere aren't overwritten.
+pub async fn refresh_collection_details(
+    api: &ApiClient,
+    collection_id: &str,
+) -> Result<Collection> {
+    api.invalidate(&format!(
+        "https://api.modrinth.com/v3/collection/{}",
+        collection_id
+    ))
+    .await?;
+
+    get_collection_details(api, collection_id).await
+}
+
+/// Creates a new collection owned by the token's user.
+pub async fn create_collection(
+    api: &ApiClient,
+    name: &str,
+    description: Option<&str>,
+    projects: Vec<String>,
+) -> Result<Collection> {
+    let body = serde_json::json!({
+        "name": name,
+        "description": description,
+        "projects": projects,
+    });
+
+    let resp = api
+        .send_json(
+            Method::POST,
+            "https://api.modrinth.com/v3/collection",
+            &body,
         )
-        .send()
         .await?;
 
-    if resp.status().is_success() {
-        let collection: Collection = resp.json().await?;
-        Ok(collection)
+    if resp.status.is_success() {
+        Ok(serde_json::from_str(&resp.body)?)
     } else {
-        Err(anyhow!(format!("Failed with status: {}", resp.status())))
+        Err(anyhow!(format!(
+            "Creating the collection failed with status {}: {}",
+            resp.status, resp.body
+        )))
+    }
+}
+
+/// Replaces the project list of a collection.
+pub async fn set_collection_projects(
+    api: &ApiClient,
+    collection_id: &str,
+    projects: Vec<String>,
+) -> Result<()> {
+    let url = format!("https://api.modrinth.com/v3/collection/{}", collection_id);
+    let body = serde_json::json!({ "new_projects": projects });
+
+    let resp = api.send_json(Method::PATCH, &url, &body).await?;
+
+    if resp.status.is_success() {
+        Ok(())
+    } else {
+        Err(anyhow!(format!(
+            "Updating collection {collection_id} failed with status {}: {}",
+            resp.status, resp.body
+        )))
     }
 }
 
 /// Get the mod downloads
 pub async fn get_mod_links(
+    api: &ApiClient,
     mod_ids: Vec<String>,
     loader: String,
     version: String,
@@ -64,35 +366,23 @@ pub async fn get_mod_links(
     let mut links: Vec<ModrinthProject> = vec![];
     let mut failed_downloads: Vec<String> = vec![];
 
-    let client = Client::new();
     for mod_id in mod_ids {
         let url = format!(
             "https://api.modrinth.com/v2/project/{}/version?loaders=[\"{}\"]&game_versions=[\"{}\"]",
             mod_id, loader, version
         );
 
-        println!("{}", url.clone());
+        say!("{}", url.clone());
 
-        let res = client
-            .get(&url)
-            .header(
-                USER_AGENT,
-                "kay-xr/modrinth_collection_downloader/0.1.0 (archangel@angelware.net)",
-            )
-            .send()
-            .await?;
+        let res = api.get(&url).await?;
 
-        if !res.status().is_success() {
+        if !res.status.is_success() {
             failed_downloads.push(mod_id);
 
-            println!(
-                "Download failed with code {}:\n{}",
-                res.status(),
-                res.text().await?
-            );
+            say!("Download failed with code {}:\n{}", res.status, res.body);
             continue;
         } else {
-            let json: Vec<ProjectVersion> = res.json().await?;
+            let json: Vec<ProjectVersion> = serde_json::from_str(&res.body)?;
 
             // pick the latest
             let latest = json.iter().max_by(|a, b| {
@@ -100,18 +390,7 @@ pub async fn get_mod_links(
             });
 
             if let Some(ver) = latest {
-                if let Some(file) = ver
-                    .files
-                    .iter()
-                    .find(|f| f.primary)
-                    .or_else(|| ver.files.first())
-                {
-                    let proj: ModrinthProject = ModrinthProject {
-                        id: mod_id,
-                        name: file.filename.clone(),
-                        download_link: file.url.clone(),
-                    };
-
+                if let Some(proj) = ModrinthProject::from_version(&mod_id, ver) {
                     links.push(proj);
                     // links.push(file.url.clone());
                 } else {
@@ -126,49 +405,192 @@ pub async fn get_mod_links(
     Ok((links, failed_downloads))
 }
 
-pub async fn log_project_name(mod_id: String) -> Result<()> {
+/// Explains how to configure a token, for errors caused by a missing one.
+pub fn missing_token_message(problem: &str) -> String {
+    let credentials = credentials_path()
+        .map(|p| format!(", or the credentials file at {}", p.display()))
+        .unwrap_or_default();
+
+    format!(
+        "{problem}. Private and unlisted content needs a Modrinth personal access token, set it with the {TOKEN_ENV} environment variable, `api.token` in config.json{credentials}."
+    )
+}
+
+/// Get a single version, either by its ID or by project and version number.
+pub async fn get_version(
+    api: &ApiClient,
+    project: Option<&str>,
+    version: &str,
+) -> Result<ProjectVersion> {
+    let url = match project {
+        Some(project) => format!(
+            "https://api.modrinth.com/v2/project/{}/version/{}",
+            project, version
+        ),
+        None => format!("https://api.modrinth.com/v2/version/{}", version),
+    };
+
+    let resp = api.get(&url).await?;
+
+    if resp.status.is_success() {
+        Ok(serde_json::from_str(&resp.body)?)
+    } else {
+        Err(anyhow!(format!("Failed with status: {}", resp.status)))
+    }
+}
+
+/// IDs of all projects published by a user.
+pub async fn get_user_projects(api: &ApiClient, user: &str) -> Result<Vec<String>> {
+    get_project_ids(
+        api,
+        &format!("https://api.modrinth.com/v2/user/{}/projects", user),
+    )
+    .await
+}
+
+/// IDs of all projects owned by an organization.
+pub async fn get_organization_projects(api: &ApiClient, organization: &str) -> Result<Vec<String>> {
+    get_project_ids(
+        api,
+        &format!(
+            "https://api.modrinth.com/v3/organization/{}/projects",
+            organization
+        ),
+    )
+    .await
+}
+
+/// IDs of all projects a user follows. Modrinth only shows these to the user themselves.
+pub async fn get_followed_projects(api: &ApiClient, user: &str) -> Result<Vec<String>> {
+    get_project_ids(
+        api,
+        &format!("https://api.modrinth.com/v2/user/{}/follows", user),
+    )
+    .await
+}
+
+async fn get_project_ids(api: &ApiClient, url: &str) -> Result<Vec<String>> {
+    let resp = api.get(url).await?;
+
+    if resp.status.is_success() {
+        let projects: Vec<ProjectRef> = serde_json::from_str(&resp.body)?;
+        Ok(projects.into_iter().map(|p| p.id).collect())
+    } else if !api.has_token() && resp.status == StatusCode::UNAUTHORIZED {
+        Err(anyhow!(missing_token_message(&format!(
+            "{url} needs authentication"
+        ))))
+    } else {
+        Err(anyhow!(format!("Failed with status: {}", resp.status)))
+    }
+}
+
+/// Get a single project.
+pub async fn get_project(api: &ApiClient, mod_id: &str) -> Result<Project> {
+    find_project(api, mod_id)
+        .await?
+        .ok_or_else(|| anyhow!(format!("Failed with status: {}", StatusCode::NOT_FOUND)))
+}
+
+/// Like [`get_project`], but a project that doesn't exist is `None` instead of an error.
+pub async fn find_project(api: &ApiClient, mod_id: &str) -> Result<Option<Project>> {
     let url = format!("https://api.modrinth.com/v2/project/{}", mod_id);
 
-    let client = Client::new();
-    let resp = client
-        .get(&url)
-        .header(
-            USER_AGENT,
-            "kay-xr/modrinth_collection_downloader/0.1.0 (archangel@angelware.net)",
-        )
-        .send()
-        .await?;
+    let resp = api.get(&url).await?;
+
+    if resp.status.is_success() {
+        Ok(Some(serde_json::from_str(&resp.body)?))
+    } else if resp.status == StatusCode::NOT_FOUND {
+        Ok(None)
+    } else {
+        Err(anyhow!(format!("Failed with status: {}", resp.status)))
+    }
+}
 
-    if resp.status().is_success() {
-        let collection: Project = resp.json().await?;
+/// Every version of a project, across all loaders and game versions.
+pub async fn get_project_versions(api: &ApiClient, mod_id: &str) -> Result<Vec<ProjectVersion>> {
+    let url = format!("https://api.modrinth.com/v2/project/{}/version", mod_id);
+
+    let resp = api.get(&url).await?;
+
+    if resp.status.is_success() {
+        Ok(serde_json::from_str(&resp.body)?)
+    } else {
+        Err(anyhow!(format!("Failed with status: {}", resp.status)))
+    }
+}
 
-        println!(
-            "{}, https://modrinth.com/mod/{}",
-            collection.title, collection.slug
+/// Get many projects at once, in chunks so the URL stays a sane length.
+pub async fn get_projects(api: &ApiClient, ids: &[String]) -> Result<Vec<Project>> {
+    let mut projects = Vec::new();
+
+    for chunk in ids.chunks(100) {
+        let ids = serde_json::to_string(chunk)?;
+        let url = format!(
+            "https://api.modrinth.com/v2/projects?ids={}",
+            urlencoding::encode(&ids)
         );
 
-        Ok(())
+        let resp = api.get(&url).await?;
+
+        if resp.status.is_success() {
+            let mut chunk: Vec<Project> = serde_json::from_str(&resp.body)?;
+            projects.append(&mut chunk);
+        } else {
+            return Err(anyhow!(format!("Failed with status: {}", resp.status)));
+        }
+    }
+
+    Ok(projects)
+}
+
+/// Get a user by id or username.
+pub async fn get_user(api: &ApiClient, user: &str) -> Result<User> {
+    let url = format!("https://api.modrinth.com/v2/user/{}", user);
+
+    let resp = api.get(&url).await?;
+
+    if resp.status.is_success() {
+        Ok(serde_json::from_str(&resp.body)?)
     } else {
-        Err(anyhow!(format!("Failed with status: {}", resp.status())))
+        Err(anyhow!(format!("Failed with status: {}", resp.status)))
     }
 }
 
-/// Extracts a collection ID from a url, if there is no matching prefix, we just assume it's already
-/// an ID.
-fn extract_collection_id(input: String) -> Result<String> {
-    let prefix = "https://modrinth.com/collection/";
+/// Members of many teams at once, one list per team. Use `team_id` to tell them apart.
+pub async fn get_teams(api: &ApiClient, team_ids: &[String]) -> Result<Vec<Vec<TeamMember>>> {
+    let mut teams = Vec::new();
+
+    for chunk in team_ids.chunks(100) {
+        let ids = serde_json::to_string(chunk)?;
+        let url = format!(
+            "https://api.modrinth.com/v2/teams?ids={}",
+            urlencoding::encode(&ids)
+        );
+
+        let resp = api.get(&url).await?;
 
-    if input.starts_with(prefix) {
-        let id = &input[prefix.len()..];
-        if id.is_empty() {
-            Err(anyhow!("Collection ID is missing"))
+        if resp.status.is_success() {
+            let mut chunk: Vec<Vec<TeamMember>> = serde_json::from_str(&resp.body)?;
+            teams.append(&mut chunk);
         } else {
-            Ok(id.to_string())
+            return Err(anyhow!(format!("Failed with status: {}", resp.status)));
         }
-    } else if !input.trim().is_empty() {
-        Ok(input.to_string())
+    }
+
+    Ok(teams)
+}
+
+/// Display name of an organization.
+pub async fn get_organization_name(api: &ApiClient, organization: &str) -> Result<String> {
+    let url = format!("https://api.modrinth.com/v3/organization/{}", organization);
+
+    let resp = api.get(&url).await?;
+
+    if resp.status.is_success() {
+        let organization: Organization = serde_json::from_str(&resp.body)?;
+        Ok(organization.name)
     } else {
-        Err(anyhow!("Invalid collection input"))
+        Err(anyhow!(format!("Failed with status: {}", resp.status)))
     }
 }
 
@@ -227,22 +649,71 @@ pub struct Hashes {
     pub sha512: String,
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize)]
+/// A dependency of a version, either on a project or on a specific version.
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Dependency {
     pub version_id: Option<String>,
-    pub project_id: String,
+    pub project_id: Option<String>,
     pub file_name: Option<String>,
     pub dependency_type: String,
 }
 
-/// Project names, we only use the title in this project.
+/// Project details, we mostly use the title and type in this project.
 #[allow(dead_code)]
 #[derive(Deserialize)]
 pub struct Project {
     pub id: String,
     pub title: String,
     pub slug: String,
+    pub project_type: String,
+    /// `required`, `optional`, `unsupported` or `unknown`.
+    #[serde(default)]
+    pub client_side: String,
+    #[serde(default)]
+    pub server_side: String,
+    #[serde(default)]
+    pub license: Option<License>,
+    /// Id of the team that owns the project.
+    #[serde(default)]
+    pub team: String,
+    /// Set when an organization owns the project, its team is often empty then.
+    #[serde(default)]
+    pub organization: Option<String>,
+}
+
+/// License of a project, `id` is an SPDX identifier or `LicenseRef-...` for custom ones.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct License {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    pub url: Option<String>,
+}
+
+/// A member of a project team, we only need the name.
+#[allow(dead_code)]
+#[derive(Deserialize)]
+pub struct TeamMember {
+    pub team_id: String,
+    pub user: User,
+    pub role: String,
+}
+
+#[derive(Deserialize)]
+pub struct User {
+    pub username: String,
+}
+
+/// Organizations only matter for their name here.
+#[derive(Deserialize)]
+struct Organization {
+    name: String,
+}
+
+/// Project listings, v2 and v3 share the `id` field and that's all we need.
+#[derive(Deserialize)]
+struct ProjectRef {
+    id: String,
 }
 
 /// Container for Mod details
@@ -252,4 +723,41 @@ pub struct ModrinthProject {
     pub id: String,
     pub name: String,
     pub download_link: String,
+    /// Empty in logs written before hashes were recorded.
+    #[serde(default)]
+    pub sha512: String,
+    #[serde(default)]
+    pub version_number: String,
+    /// File size in bytes.
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub version_id: String,
+    #[serde(default)]
+    pub sha1: String,
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+}
+
+impl ModrinthProject {
+    /// Uses the primary file of a version, or the first one if none is marked primary.
+    pub fn from_version(id: &str, version: &ProjectVersion) -> Option<ModrinthProject> {
+        let file = version
+            .files
+            .iter()
+            .find(|f| f.primary)
+            .or_else(|| version.files.first())?;
+
+        Some(ModrinthProject {
+            id: id.to_string(),
+            name: file.filename.clone(),
+            download_link: file.url.clone(),
+            sha512: file.hashes.sha512.clone(),
+            version_number: version.version_number.clone(),
+            size: file.size,
+            version_id: version.id.clone(),
+            sha1: file.hashes.sha1.clone(),
+            dependencies: version.dependencies.clone(),
+        })
+    }
 }
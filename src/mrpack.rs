@@ -0,0 +1,512 @@
+// Modpack support, installs the files and overrides of a Modrinth modpack (.mrpack).
+
+use crate::cache::Cache;
+use crate::config::Config;
+use crate::download::{DownloadOutcome, download_files, file_name};
+use crate::modrinth::{ApiClient, ModrinthProject, get_mod_links};
+use anyhow::{Context, Result, anyhow};
+use async_zip::StoredZipEntry;
+use async_zip::tokio::read::seek::ZipFileReader;
+use colored::Colorize;
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+use tokio::io::BufReader;
+
+/// Hosts a `.mrpack` may download files from, per the format.
+const ALLOWED_HOSTS: &[&str] = &[
+    "cdn.modrinth.com",
+    "github.com",
+    "raw.githubusercontent.com",
+    "gitlab.com",
+];
+
+/// Override folders applied on top of the instance, in order. This tool sets up servers, so the
+/// server specific overrides win over the shared ones.
+const OVERRIDE_DIRS: &[&str] = &["overrides/", "server-overrides/"];
+
+/// A modpack version resolved into its files.
+pub struct Modpack {
+    pub name: String,
+    /// The `.mrpack` file of the picked version.
+    pub file: ModrinthProject,
+    /// Downloaded `.mrpack`, needed later to extract the overrides. `None` in a dry run, which
+    /// doesn't download it and so knows nothing about its files.
+    pub mrpack: Option<PathBuf>,
+    /// Files that go into `mods/`.
+    pub mods: Vec<ModrinthProject>,
+    /// Every other file, keyed by its directory relative to the instance root.
+    pub other: BTreeMap<PathBuf, Vec<ModrinthProject>>,
+}
+
+/// Picks the version of every modpack matching the loader and Minecraft version, downloads its
+/// `.mrpack` and reads the file list. Returns the modpacks and the ids without a compatible
+/// version or whose `.mrpack` failed to download. A dry run stops after picking the versions and
+/// leaves the files empty.
+pub async fn resolve_modpacks(
+    api: &ApiClient,
+    ids: Vec<String>,
+    loader: String,
+    version: String,
+    config: &Config,
+    cache: Option<Arc<Cache>>,
+    dry_run: bool,
+) -> Result<(Vec<Modpack>, Vec<String>)> {
+    if ids.is_empty() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+
+    let (packs, mut failed) = get_mod_links(api, ids, loader.clone(), version.clone()).await?;
+
+    if dry_run {
+        let modpacks = packs
+            .into_iter()
+            .map(|file| Modpack {
+                name: file.name.trim_end_matches(".mrpack").to_string(),
+                file,
+                mrpack: None,
+                mods: Vec::new(),
+                other: BTreeMap::new(),
+            })
+            .collect();
+        return Ok((modpacks, failed));
+    }
+
+    let mut modpacks = Vec::new();
+
+    // The cache is used by hand, download_files would register the temporary directory as a pack
+    let dir = download_dir();
+    fs::create_dir_all(&dir).await?;
+    if let Some(cache) = &cache {
+        for pack in &packs {
+            cache
+                .link_into(&pack.sha512, &dir.join(file_name(pack)?))
+                .await?;
+        }
+    }
+
+    for result in download_files(packs, &dir, config, None).await? {
+        let mrpack = match result.outcome {
+            DownloadOutcome::Downloaded { path } | DownloadOutcome::UpToDate { path } => path,
+            _ => {
+                say!(
+                    "{}",
+                    format!("Downloading modpack {} failed", result.project.name).bright_red()
+                );
+                failed.push(result.project.id);
+                continue;
+            }
+        };
+        if let Some(cache) = &cache
+            && let Err(e) = cache.store(&result.project.sha512, &mrpack).await
+        {
+            say!("Could not cache {}: {e}", result.project.name);
+        }
+
+        match read_modpack(&mrpack, &loader, &version).await {
+            Ok((name, mods, other)) => modpacks.push(Modpack {
+                name,
+                file: result.project,
+                mrpack: Some(mrpack),
+                mods,
+                other,
+            }),
+            Err(e) => {
+                say!(
+                    "{}",
+                    format!("Modpack {} can't be used: {e:#}", result.project.name).bright_red()
+                );
+                failed.push(result.project.id);
+            }
+        }
+    }
+
+    Ok((modpacks, failed))
+}
+
+/// Name, mod files and other files of a downloaded `.mrpack`. Fails on anything that could write
+/// outside the instance or download from a host the format doesn't allow.
+async fn read_modpack(
+    mrpack: &Path,
+    loader: &str,
+    version: &str,
+) -> Result<(
+    String,
+    Vec<ModrinthProject>,
+    BTreeMap<PathBuf, Vec<ModrinthProject>>,
+)> {
+    let index = read_index(mrpack).await?;
+    check_dependencies(&index, loader, version);
+
+    let mut mods = Vec::new();
+    let mut other: BTreeMap<PathBuf, Vec<ModrinthProject>> = BTreeMap::new();
+
+    for file in index.files {
+        if file
+            .env
+            .as_ref()
+            .is_some_and(|env| env.server == "unsupported")
+        {
+            continue;
+        }
+
+        let path = safe_path(&file.path)?;
+        let project = file.into_project(&path)?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        if dir == Path::new("mods") {
+            mods.push(project);
+        } else {
+            other.entry(dir).or_default().push(project);
+        }
+    }
+
+    Ok((index.name, mods, other))
+}
+
+/// Where `.mrpack` files are downloaded to before reading them, one directory per run.
+pub fn download_dir() -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "modrinth-collection-downloader-{}",
+        std::process::id()
+    ))
+}
+
+/// Removes the `.mrpack` files this run downloaded, once their overrides are installed.
+pub async fn remove_downloads() -> Result<()> {
+    match fs::remove_dir_all(download_dir()).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Extracts the override folders of a modpack into the instance root. Returns the number of files
+/// written.
+pub async fn install_overrides(mrpack: &Path, root: &Path) -> Result<usize> {
+    let file = fs::File::open(mrpack).await?;
+    let mut buf = BufReader::new(file);
+    let mut reader = ZipFileReader::with_tokio(&mut buf)
+        .await
+        .context("opening mrpack")?;
+
+    let entries = override_entries(reader.file().entries())?;
+    for (idx, path) in &entries {
+        let target = root.join(path);
+
+        let mut contents = Vec::new();
+        let mut entry_reader = reader.reader_with_entry(*idx).await?;
+        entry_reader
+            .read_to_end_checked(&mut contents)
+            .await
+            .with_context(|| format!("reading {}", path.display()))?;
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&target, contents).await?;
+    }
+
+    Ok(entries.len())
+}
+
+/// Paths, relative to the instance root, that [`install_overrides`] would write.
+pub async fn override_files(mrpack: &Path) -> Result<Vec<PathBuf>> {
+    let file = fs::File::open(mrpack).await?;
+    let mut buf = BufReader::new(file);
+    let reader = ZipFileReader::with_tokio(&mut buf)
+        .await
+        .context("opening mrpack")?;
+
+    Ok(override_entries(reader.file().entries())?
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect())
+}
+
+/// Zip entry indices of the override files in the order they are applied, with their target path.
+fn override_entries(entries: &[StoredZipEntry]) -> Result<Vec<(usize, PathBuf)>> {
+    let mut found = Vec::new();
+
+    for prefix in OVERRIDE_DIRS {
+        for (idx, entry) in entries.iter().enumerate() {
+            let Ok(name) = entry.filename().as_str() else {
+                continue;
+            };
+            if let Some(path) = name.strip_prefix(prefix)
+                && !name.ends_with('/')
+            {
+                found.push((idx, safe_path(path)?));
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+async fn read_index(mrpack: &Path) -> Result<MrpackIndex> {
+    let file = fs::File::open(mrpack).await?;
+    let mut buf = BufReader::new(file);
+    let mut reader = ZipFileReader::with_tokio(&mut buf)
+        .await
+        .context("opening mrpack")?;
+
+    let idx = reader
+        .file()
+        .entries()
+        .iter()
+        .position(|e| {
+            e.filename()
+                .as_str()
+                .is_ok_and(|n| n == "modrinth.index.json")
+        })
+        .context("modrinth.index.json not found in mrpack")?;
+
+    let mut contents = String::new();
+    let mut entry_reader = reader.reader_with_entry(idx).await?;
+    entry_reader
+        .read_to_string_checked(&mut contents)
+        .await
+        .context("reading modrinth.index.json")?;
+
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Warns when the modpack was built for a different loader or Minecraft version than we asked
+/// for.
+fn check_dependencies(index: &MrpackIndex, loader: &str, version: &str) {
+    if let Some(minecraft) = index.dependencies.get("minecraft")
+        && minecraft != version
+    {
+        say!(
+            "{}",
+            format!(
+                "Warning: {} is built for Minecraft {minecraft}, not {version}",
+                index.name
+            )
+            .bright_red()
+        );
+    }
+
+    let loader_key = match loader {
+        "fabric" => "fabric-loader",
+        "quilt" => "quilt-loader",
+        other => other,
+    };
+    if !index.dependencies.contains_key(loader_key) {
+        say!(
+            "{}",
+            format!(
+                "Warning: {} doesn't list {loader} as its loader",
+                index.name
+            )
+            .bright_red()
+        );
+    }
+}
+
+/// Rejects absolute paths and `..`, so a modpack can't write outside the instance. Backslashes
+/// and drive letters are rejected too, they only mean something on Windows and paths in a
+/// modpack always use `/`.
+fn safe_path(path: &str) -> Result<PathBuf> {
+    let windows_only = path.contains(['\\', ':']);
+    let path = PathBuf::from(path);
+    if !windows_only
+        && path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        Ok(path)
+    } else {
+        Err(anyhow!(
+            "Modpack contains an unsafe path: {}",
+            path.display()
+        ))
+    }
+}
+
+/// `modrinth.index.json` schema.
+#[derive(Deserialize)]
+struct MrpackIndex {
+    name: String,
+    files: Vec<MrpackFile>,
+    dependencies: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct MrpackFile {
+    path: String,
+    hashes: MrpackHashes,
+    env: Option<MrpackEnv>,
+    downloads: Vec<String>,
+    #[serde(rename = "fileSize", default)]
+    file_size: u64,
+}
+
+#[derive(Deserialize)]
+struct MrpackHashes {
+    sha1: String,
+    sha512: String,
+}
+
+#[derive(Deserialize)]
+struct MrpackEnv {
+    server: String,
+}
+
+impl MrpackFile {
+    /// Files hosted on Modrinth carry their project id in the URL
+    /// (`cdn.modrinth.com/data/<project>/versions/...`), others fall back to the file name.
+    fn into_project(self, path: &Path) -> Result<ModrinthProject> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow!("Modpack file without a name: {}", self.path))?;
+        let download_link = self
+            .downloads
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Modpack file {} has no download", self.path))?;
+        let url = Url::parse(&download_link)
+            .with_context(|| format!("Modpack file {} has an invalid download", self.path))?;
+        if url.scheme() != "https" || !url.host_str().is_some_and(|h| ALLOWED_HOSTS.contains(&h)) {
+            return Err(anyhow!(
+                "Modpack file {} downloads from {download_link}, which modpacks may not use",
+                self.path
+            ));
+        }
+
+        let id = download_link
+            .split_once("cdn.modrinth.com/data/")
+            .and_then(|(_, rest)| rest.split('/').next())
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| name.clone());
+
+        Ok(ModrinthProject {
+            id,
+            name,
+            download_link,
+            sha512: self.hashes.sha512,
+            version_number: String::new(),
+            size: self.file_size,
+            version_id: String::new(),
+            sha1: self.hashes.sha1,
+            dependencies: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_zip::base::read::mem::ZipFileReader as MemZipReader;
+    use async_zip::base::write::ZipFileWriter;
+    use async_zip::{Compression, ZipEntryBuilder};
+
+    #[test]
+    fn rejects_unsafe_paths() {
+        assert_eq!(
+            safe_path("mods/sodium.jar").unwrap(),
+            Path::new("mods/sodium.jar")
+        );
+        assert!(safe_path("./config/sodium.json").is_ok());
+
+        for path in [
+            "../mods/evil.jar",
+            "mods/../../evil.jar",
+            "/etc/cron.d/evil",
+            "C:\\Windows\\evil.dll",
+            "C:/Windows/evil.dll",
+            "mods\\..\\..\\evil.jar",
+        ] {
+            assert!(safe_path(path).is_err(), "{path} was accepted");
+        }
+    }
+
+    fn file(url: &str) -> MrpackFile {
+        MrpackFile {
+            path: "mods/sodium.jar".to_string(),
+            hashes: MrpackHashes {
+                sha1: "160".to_string(),
+                sha512: "512".to_string(),
+            },
+            env: None,
+            downloads: vec![url.to_string()],
+            file_size: 1,
+        }
+    }
+
+    #[test]
+    fn only_downloads_from_allowed_hosts() {
+        let path = Path::new("mods/sodium.jar");
+        let project = file("https://cdn.modrinth.com/data/AANobbMI/versions/abc/sodium.jar")
+            .into_project(path)
+            .unwrap();
+        assert_eq!(project.id, "AANobbMI");
+        assert!(
+            file("https://github.com/a/b/releases/download/1/sodium.jar")
+                .into_project(path)
+                .is_ok()
+        );
+
+        for url in [
+            "https://evil.example/sodium.jar",
+            "http://cdn.modrinth.com/data/AANobbMI/sodium.jar",
+            "https://cdn.modrinth.com.evil.example/sodium.jar",
+            "file:///etc/passwd",
+            "not a url",
+        ] {
+            assert!(file(url).into_project(path).is_err(), "{url} was accepted");
+        }
+    }
+
+    async fn zip(names: &[&str]) -> Vec<StoredZipEntry> {
+        let mut writer = ZipFileWriter::new(Vec::new());
+        for name in names {
+            let entry = ZipEntryBuilder::new(name.to_string().into(), Compression::Stored);
+            writer.write_entry_whole(entry, b"x").await.unwrap();
+        }
+        let bytes = writer.close().await.unwrap();
+
+        MemZipReader::new(bytes)
+            .await
+            .unwrap()
+            .file()
+            .entries()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn applies_server_overrides_last() {
+        let entries = zip(&[
+            "modrinth.index.json",
+            "server-overrides/config/a.toml",
+            "overrides/config/",
+            "overrides/config/a.toml",
+        ])
+        .await;
+
+        let found = override_entries(&entries).unwrap();
+        assert_eq!(
+            found,
+            [
+                (3, PathBuf::from("config/a.toml")),
+                (1, PathBuf::from("config/a.toml"))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_overrides_outside_the_instance() {
+        for name in [
+            "overrides/../evil.jar",
+            "server-overrides//etc/evil",
+            "overrides/C:\\evil",
+        ] {
+            let entries = zip(&["overrides/config/a.toml", name]).await;
+            assert!(override_entries(&entries).is_err(), "{name} was accepted");
+        }
+    }
+}
@@ -0,0 +1,73 @@
+// Output mode, human readable text or JSON events for scripts.
+
+use indicatif::ProgressDrawTarget;
+use serde::Serialize;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Prints a human readable message. In JSON mode it goes to stderr, so stdout only carries
+/// events.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::output::is_json() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+/// Switches to JSON mode: events on stdout, everything else on stderr without colors, no progress
+/// bars and no prompts.
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+    if json {
+        colored::control::set_override(false);
+    }
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// One event, printed as a single line of JSON.
+#[derive(Serialize)]
+struct Event<'a, T: Serialize> {
+    event: &'a str,
+    #[serde(flatten)]
+    data: &'a T,
+}
+
+/// Emits an event in JSON mode, does nothing otherwise. `data` has to serialize to an object,
+/// its fields end up next to `event`.
+pub fn event<T: Serialize>(event: &str, data: &T) {
+    if !is_json() {
+        return;
+    }
+
+    match serde_json::to_string(&Event { event, data }) {
+        Ok(line) => println!("{line}"),
+        Err(e) => eprintln!("Could not serialize {event} event: {e}"),
+    }
+}
+
+/// Where progress bars are drawn, nowhere in JSON mode.
+pub fn progress_target() -> ProgressDrawTarget {
+    if is_json() {
+        ProgressDrawTarget::hidden()
+    } else {
+        ProgressDrawTarget::stderr()
+    }
+}
+
+/// Stdout for child processes like packwiz and the server installers, redirected to stderr in
+/// JSON mode.
+pub fn child_stdout() -> Stdio {
+    if is_json() {
+        Stdio::from(std::io::stderr())
+    } else {
+        Stdio::inherit()
+    }
+}
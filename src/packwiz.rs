@@ -1,8 +1,15 @@
-// Basic packwiz creation, gets you started at least.
+// packwiz packs. The metadata is written directly, the packwiz binary is only needed to export a
+// .mrpack from it.
 
-use crate::modrinth::ModrinthProject;
+use crate::modrinth::{ApiClient, ModrinthProject, Project, get_mod_links, get_projects};
+use crate::output;
 use anyhow::{Context, Result};
-use std::path::PathBuf;
+use clap::ValueEnum;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use tokio::fs;
 use tokio::io::BufReader;
@@ -13,25 +20,140 @@ const BIN_NAME: &str = "packwiz.exe";
 #[cfg(not(target_os = "windows"))]
 const BIN_NAME: &str = "packwiz";
 
+/// Prebuilt packwiz nightlies, only published for some platforms.
 #[cfg(target_os = "windows")]
-const ZIP_URL: &str = "https://nightly.link/packwiz/packwiz/workflows/go/main/Windows%2064-bit.zip";
+const ZIP_URL: Option<&str> =
+    Some("https://nightly.link/packwiz/packwiz/workflows/go/main/Windows%2064-bit.zip");
 #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-const ZIP_URL: &str =
-    "https://nightly.link/packwiz/packwiz/workflows/go/main/Linux%2064-bit%20x86.zip";
+const ZIP_URL: Option<&str> =
+    Some("https://nightly.link/packwiz/packwiz/workflows/go/main/Linux%2064-bit%20x86.zip");
+#[cfg(not(any(
+    target_os = "windows",
+    all(target_os = "linux", target_arch = "x86_64")
+)))]
+const ZIP_URL: Option<&str> = None;
 
-pub async fn init_packwiz(mut dir: PathBuf) -> Result<()> {
+/// Version of the packwiz metadata format we write.
+const PACK_FORMAT: &str = "packwiz:1.1.0";
+
+/// First line of every metafile we write. Only tagged metafiles are removed when their project
+/// leaves the pack, the ones added with `packwiz mr install` and the like are left alone.
+const METAFILE_TAG: &str = "# Written by modrinth-collection-downloader\n";
+
+/// What goes into pack.toml besides the index.
+pub struct PackInfo {
+    pub name: String,
+    pub author: String,
+    pub version: String,
+    pub minecraft: String,
+    pub loader: String,
+    /// Left out of `[versions]` when unknown.
+    pub loader_version: Option<String>,
+}
+
+/// A file of the pack, `path` is relative to the pack directory.
+pub struct PackFile {
+    pub path: String,
+    pub contents: String,
+}
+
+/// What happens to required dependencies that aren't part of the pack, the question
+/// `packwiz mr install` would ask.
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyPolicy {
+    /// Leave them out without comment.
+    Skip,
+    /// Leave them out and list them in the report.
+    #[default]
+    Warn,
+    /// Add their newest compatible version to the pack.
+    Install,
+}
+
+/// Outcome of a pack build. Projects that can't be added don't stop the build, they end up here.
+#[derive(Serialize, Default)]
+pub struct PackReport {
+    pub dir: PathBuf,
+    pub metafiles: usize,
+    /// Files in index.toml, metafiles included.
+    pub indexed: usize,
+    pub failures: Vec<PackFailure>,
+    /// Required dependencies that aren't in the pack, with the projects that need them.
+    pub missing_dependencies: BTreeMap<String, Vec<String>>,
+    /// Dependencies added to the pack by the install policy.
+    pub added_dependencies: Vec<String>,
+}
+
+/// A project that was left out of the pack.
+#[derive(Serialize)]
+pub struct PackFailure {
+    pub id: String,
+    pub reason: String,
+}
+
+impl PackReport {
+    pub fn print(&self) {
+        say!(
+            "{}",
+            format!(
+                "Wrote {} metafiles to {}, {} files in the index",
+                self.metafiles,
+                self.dir.display(),
+                self.indexed
+            )
+            .bright_green()
+        );
+        if !self.added_dependencies.is_empty() {
+            say!(
+                "Added {} dependencies: {}",
+                self.added_dependencies.len(),
+                self.added_dependencies.join(", ")
+            );
+        }
+        for (id, needed_by) in &self.missing_dependencies {
+            say!(
+                "{}",
+                format!(
+                    "Missing dependency {id}, needed by {}",
+                    needed_by.join(", ")
+                )
+                .bright_red()
+            );
+        }
+        for failure in &self.failures {
+            say!(
+                "{}",
+                format!("Left out {}: {}", failure.id, failure.reason).bright_red()
+            );
+        }
+    }
+}
+
+/// Finds a packwiz binary to export with: one on the PATH, one downloaded earlier or a freshly
+/// downloaded nightly. `None` when there is none and no prebuilt one for this platform.
+pub async fn init_packwiz(mut dir: PathBuf) -> Result<Option<PathBuf>> {
     dir.pop();
     let packwiz_dir = dir.join("packwiz");
     fs::create_dir_all(&packwiz_dir).await?;
 
     if command_works("packwiz").await {
-        return Ok(());
+        return Ok(Some(PathBuf::from("packwiz")));
+    }
+
+    let out_path = packwiz_dir.join(BIN_NAME);
+    if fs::try_exists(&out_path).await? {
+        return Ok(Some(out_path));
     }
 
+    let Some(zip_url) = ZIP_URL else {
+        return Ok(None);
+    };
+
     // If we don't have packwiz installed we can download the binary, thanks to their cool static url :)
-    let bytes = reqwest::get(ZIP_URL)
+    let bytes = reqwest::get(zip_url)
         .await
-        .with_context(|| format!("GET {}", ZIP_URL))?
+        .with_context(|| format!("GET {}", zip_url))?
         .bytes()
         .await
         .context("reading zip payload")?;
@@ -50,11 +172,11 @@ pub async fn init_packwiz(mut dir: PathBuf) -> Result<()> {
     let entries = reader.file().entries();
     let mut target_idx = None;
     for (i, e) in entries.iter().enumerate() {
-        if let Ok(name) = e.filename().as_str() {
-            if name.ends_with(BIN_NAME) {
-                target_idx = Some(i);
-                break;
-            }
+        if let Ok(name) = e.filename().as_str()
+            && name.ends_with(BIN_NAME)
+        {
+            target_idx = Some(i);
+            break;
         }
     }
     let idx = target_idx.context("packwiz binary not found in zip")?;
@@ -70,7 +192,6 @@ pub async fn init_packwiz(mut dir: PathBuf) -> Result<()> {
     }
 
     // write out binary
-    let out_path = packwiz_dir.join(BIN_NAME);
     fs::write(&out_path, &bin_bytes).await?;
 
     // chmod on unix
@@ -83,74 +204,529 @@ pub async fn init_packwiz(mut dir: PathBuf) -> Result<()> {
     }
 
     // verify it runs
-    let ok = Command::new(&out_path).arg("-h").status().await?.success();
+    let ok = Command::new(&out_path)
+        .arg("-h")
+        .stdout(output::child_stdout())
+        .status()
+        .await?
+        .success();
     anyhow::ensure!(ok, "downloaded packwiz failed to run");
 
     // cleanup zip
     let _ = fs::remove_file(&tmp_zip).await;
 
-    Ok(())
+    Ok(Some(out_path))
 }
 
-pub async fn create_pack(mut dir: PathBuf, list: Vec<ModrinthProject>) -> Result<()> {
+/// Writes the pack into a `packwiz` directory next to the mods folder `dir`. Metafiles of
+/// projects that are no longer part of the pack are removed, then the whole directory is indexed
+/// like `packwiz refresh` does, so files added by hand are served too.
+pub async fn create_pack(
+    api: &ApiClient,
+    mut dir: PathBuf,
+    info: &PackInfo,
+    list: &[ModrinthProject],
+    dependencies: DependencyPolicy,
+) -> Result<PackReport> {
     dir.pop();
-    let exe = dir.join("packwiz").join(BIN_NAME);
-    let run_path = dir.join("packwiz");
-    let toml_path = dir.join("packwiz").join("pack.toml");
-
-    // Check if the pack is already initialized
-    if !fs::try_exists(toml_path).await? {
-        let status = Command::new(&exe)
-            .arg("init")
-            .current_dir(&run_path)
-            .stdin(Stdio::inherit())
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .status()
-            .await
-            .context("running packwiz init")?;
-        anyhow::ensure!(status.success(), "packwiz init failed");
-    } else {
-        println!("A pack.toml file already exists, skipping initialization.");
+    let pack_dir = dir.join("packwiz");
+
+    let (files, mut report) = pack_metafiles(api, info, list, dependencies).await?;
+    let wanted: HashSet<&str> = files.iter().map(|f| f.path.as_str()).collect();
+    for stale in metafiles(&pack_dir).await? {
+        let path = pack_dir.join(&stale);
+        if !wanted.contains(stale.as_str())
+            && fs::read_to_string(&path)
+                .await
+                .is_ok_and(|contents| contents.starts_with(METAFILE_TAG))
+        {
+            fs::remove_file(&path).await?;
+        }
     }
 
-    // Add mods to the pack
-    for project in list {
-        let status = Command::new(&exe)
-            .arg("mr")
-            .arg("install")
-            .arg(project.id)
-            .current_dir(&run_path)
-            .stdin(Stdio::inherit())
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .status()
-            .await
-            .context("adding mod to pack")?;
-        anyhow::ensure!(status.success(), "adding mod(s) failed");
+    for file in &files {
+        let path = pack_dir.join(&file.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&path, &file.contents).await?;
+    }
+
+    let (index, indexed) = build_index(&pack_dir).await?;
+    fs::write(pack_dir.join("index.toml"), &index).await?;
+    fs::write(pack_dir.join("pack.toml"), pack_toml(info, &sha256(&index))).await?;
+
+    report.dir = pack_dir;
+    report.metafiles = files.len();
+    report.indexed = indexed;
+    Ok(report)
+}
+
+/// Re-indexes a pack after it was edited by hand, the same as `packwiz refresh`. Returns how many
+/// files are in the index.
+pub async fn refresh_pack(pack_dir: &Path) -> Result<usize> {
+    let pack_path = pack_dir.join("pack.toml");
+    let pack = fs::read_to_string(&pack_path)
+        .await
+        .with_context(|| format!("reading {}", pack_path.display()))?;
+
+    let (index, indexed) = build_index(pack_dir).await?;
+    let pack = set_index_hash(&pack, &sha256(&index))
+        .with_context(|| format!("{} has no [index] hash", pack_path.display()))?;
+
+    fs::write(pack_dir.join("index.toml"), &index).await?;
+    fs::write(&pack_path, pack).await?;
+
+    Ok(indexed)
+}
+
+/// The `version` of the pack.toml next to the mods folder `dir`, if there is one.
+pub async fn pack_version(dir: &Path) -> Result<Option<String>> {
+    let path = dir
+        .parent()
+        .unwrap_or(dir)
+        .join("packwiz")
+        .join("pack.toml");
+    if !fs::try_exists(&path).await? {
+        return Ok(None);
     }
 
-    // Create the mrpack
-    let status = Command::new(&exe)
-        .arg("mr")
-        .arg("export")
-        .current_dir(&run_path)
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::inherit())
+    let contents = fs::read_to_string(&path).await?;
+    Ok(top_level_string(&contents, "version"))
+}
+
+/// Exports the pack in `pack_dir` as a .mrpack with the packwiz binary `exe`.
+pub async fn export_pack(exe: &Path, pack_dir: &Path) -> Result<()> {
+    let status = Command::new(exe)
+        .args(["mr", "export"])
+        .current_dir(pack_dir)
+        .stdin(Stdio::null())
+        .stdout(output::child_stdout())
         .stderr(Stdio::inherit())
         .status()
         .await
-        .context("exporting modpack")?;
-    anyhow::ensure!(status.success(), "exporting modpack failed");
+        .context("running packwiz mr export")?;
+    anyhow::ensure!(status.success(), "packwiz mr export failed");
 
     Ok(())
 }
 
+/// The metafiles [`create_pack`] writes, one per project. Slugs, project types and sides come
+/// from Modrinth, and required dependencies are handled according to `dependencies`.
+pub async fn pack_metafiles(
+    api: &ApiClient,
+    info: &PackInfo,
+    list: &[ModrinthProject],
+    dependencies: DependencyPolicy,
+) -> Result<(Vec<PackFile>, PackReport)> {
+    let mut list = list.to_vec();
+    let mut report = PackReport::default();
+
+    // Dependencies of added dependencies are looked at too, each one only once
+    let mut tried: HashSet<String> = list.iter().map(|p| p.id.clone()).collect();
+    loop {
+        let missing: BTreeMap<String, Vec<String>> = missing_dependencies(&list)
+            .into_iter()
+            .filter(|(id, _)| !tried.contains(id))
+            .collect();
+        if missing.is_empty() || dependencies == DependencyPolicy::Skip {
+            break;
+        }
+        if dependencies == DependencyPolicy::Warn {
+            report.missing_dependencies = missing;
+            break;
+        }
+
+        tried.extend(missing.keys().cloned());
+        let (links, failed) = get_mod_links(
+            api,
+            missing.keys().cloned().collect(),
+            info.loader.clone(),
+            info.minecraft.clone(),
+        )
+        .await?;
+        for id in failed {
+            report.failures.push(PackFailure {
+                reason: format!(
+                    "needed by {}, but has no {} version for Minecraft {}",
+                    missing[&id].join(", "),
+                    info.loader,
+                    info.minecraft
+                ),
+                id,
+            });
+        }
+        report
+            .added_dependencies
+            .extend(links.iter().map(|l| l.id.clone()));
+        list.extend(links);
+    }
+
+    // Slugs and project types decide where metafiles go, guessing them would move every
+    // metafile of an existing pack
+    let ids: Vec<String> = list.iter().map(|p| p.id.clone()).collect();
+    let projects: HashMap<String, Project> = get_projects(api, &ids)
+        .await
+        .context("Looking up the projects of the pack failed, the pack was left unchanged")?
+        .into_iter()
+        .map(|p| (p.id.clone(), p))
+        .collect();
+
+    let mut files: Vec<PackFile> = Vec::new();
+    for link in &list {
+        let project = projects.get(&link.id);
+        let slug = project.map_or(link.id.as_str(), |p| p.slug.as_str());
+        let folder = match project.map(|p| p.project_type.as_str()) {
+            Some("resourcepack") => "resourcepacks",
+            Some("shader") => "shaderpacks",
+            _ => "mods",
+        };
+        let path = format!("{folder}/{slug}.pw.toml");
+
+        let contents = match metafile(link, project) {
+            Ok(contents) => contents,
+            Err(reason) => {
+                report.failures.push(PackFailure {
+                    id: link.id.clone(),
+                    reason,
+                });
+                continue;
+            }
+        };
+        if files.iter().any(|f| f.path == path) {
+            report.failures.push(PackFailure {
+                id: link.id.clone(),
+                reason: format!("another project already uses {path}"),
+            });
+            continue;
+        }
+
+        files.push(PackFile { path, contents });
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok((files, report))
+}
+
+/// Required dependencies of `list` that aren't part of it, with the projects that need them.
+fn missing_dependencies(list: &[ModrinthProject]) -> BTreeMap<String, Vec<String>> {
+    let ids: HashSet<&str> = list.iter().map(|p| p.id.as_str()).collect();
+
+    let mut missing: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for project in list {
+        for dependency in &project.dependencies {
+            if dependency.dependency_type == "required"
+                && let Some(id) = &dependency.project_id
+                && !ids.contains(id.as_str())
+            {
+                missing
+                    .entry(id.clone())
+                    .or_default()
+                    .push(project.id.clone());
+            }
+        }
+    }
+
+    missing
+}
+
+/// pack.toml, pointing at an index with the hash `index_hash`.
+fn pack_toml(info: &PackInfo, index_hash: &str) -> String {
+    let mut pack = format!(
+        "name = {}\nauthor = {}\nversion = {}\npack-format = \"{PACK_FORMAT}\"\n\n[index]\nfile = \"index.toml\"\nhash-format = \"sha256\"\nhash = \"{index_hash}\"\n\n[versions]\n",
+        toml_string(&info.name),
+        toml_string(&info.author),
+        toml_string(&info.version),
+    );
+    if let Some(loader_version) = &info.loader_version {
+        pack.push_str(&format!(
+            "{} = {}\n",
+            info.loader,
+            toml_string(loader_version)
+        ));
+    }
+    pack.push_str(&format!("minecraft = {}\n", toml_string(&info.minecraft)));
+
+    pack
+}
+
+/// Hashes every file of the pack into an index.toml, returns it with the number of files. Hidden
+/// files, pack.toml, index.toml, the packwiz binary and exported .mrpack files are left out.
+async fn build_index(pack_dir: &Path) -> Result<(String, usize)> {
+    let mut files = Vec::new();
+    let mut dirs = vec![pack_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(mut entries) = fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            if entry.file_type().await?.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+
+            let relative = path
+                .strip_prefix(pack_dir)?
+                .to_string_lossy()
+                .replace('\\', "/");
+            let top_level = !relative.contains('/');
+            if top_level
+                && (["pack.toml", "index.toml", "packwiz.zip", BIN_NAME].contains(&name.as_str())
+                    || name.ends_with(".mrpack"))
+            {
+                continue;
+            }
+            files.push((relative, path));
+        }
+    }
+    files.sort();
+
+    let mut index = String::from("hash-format = \"sha256\"\n");
+    for (relative, path) in &files {
+        let contents = fs::read(path).await?;
+        index.push_str(&format!(
+            "\n[[files]]\nfile = {}\nhash = \"{:x}\"\n",
+            toml_string(relative),
+            Sha256::digest(&contents)
+        ));
+        if relative.ends_with(".pw.toml") {
+            index.push_str("metafile = true\n");
+        }
+    }
+
+    Ok((index, files.len()))
+}
+
+/// Replaces the hash in the `[index]` table of a pack.toml, `None` if it has none.
+fn set_index_hash(pack: &str, hash: &str) -> Option<String> {
+    let mut table = "";
+    let mut replaced = false;
+    let mut out = String::new();
+    for line in pack.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            table = trimmed;
+        }
+        if table == "[index]"
+            && !replaced
+            && trimmed
+                .split_once('=')
+                .is_some_and(|(key, _)| key.trim() == "hash")
+        {
+            out.push_str(&format!("hash = \"{hash}\"\n"));
+            replaced = true;
+            continue;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    replaced.then_some(out)
+}
+
+/// The `.pw.toml` of a project, the same thing `packwiz mr install` writes. Fails with the reason
+/// when the file can't be described.
+fn metafile(link: &ModrinthProject, project: Option<&Project>) -> Result<String, String> {
+    if link.download_link.is_empty() {
+        return Err("no download URL".to_string());
+    }
+    let side = match project {
+        Some(p) if p.server_side == "unsupported" => "client",
+        Some(p) if p.client_side == "unsupported" => "server",
+        _ => "both",
+    };
+    // packwiz checks Modrinth files against their sha1, older logs may only have the sha512
+    let (hash_format, hash) = match (link.sha1.is_empty(), link.sha512.is_empty()) {
+        (false, _) => ("sha1", &link.sha1),
+        (true, false) => ("sha512", &link.sha512),
+        (true, true) => return Err("no file hash".to_string()),
+    };
+
+    let mut toml = format!(
+        "{METAFILE_TAG}name = {}\nfilename = {}\nside = \"{side}\"\n\n[download]\nurl = {}\nhash-format = \"{hash_format}\"\nhash = \"{hash}\"\n",
+        toml_string(project.map_or(&link.name, |p| &p.title)),
+        toml_string(&link.name),
+        toml_string(&link.download_link),
+    );
+    if !link.version_id.is_empty() {
+        toml.push_str(&format!(
+            "\n[update]\n[update.modrinth]\nmod-id = {}\nversion = {}\n",
+            toml_string(&link.id),
+            toml_string(&link.version_id)
+        ));
+    }
+
+    Ok(toml)
+}
+
+/// `.pw.toml` files already in the pack, relative to `pack_dir`.
+async fn metafiles(pack_dir: &Path) -> Result<Vec<String>> {
+    let mut found = Vec::new();
+    for folder in ["mods", "resourcepacks", "shaderpacks"] {
+        let Ok(mut entries) = fs::read_dir(pack_dir.join(folder)).await else {
+            continue;
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.ends_with(".pw.toml") {
+                found.push(format!("{folder}/{name}"));
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// A plain string value of a top level key, good enough for the pack.toml files we write.
+fn top_level_string(toml: &str, key: &str) -> Option<String> {
+    toml.lines()
+        .take_while(|line| !line.trim_start().starts_with('['))
+        .filter_map(|line| line.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .and_then(|(_, value)| {
+            let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+            (!value.contains(['"', '\\'])).then(|| value.to_string())
+        })
+}
+
+/// A TOML basic string.
+fn toml_string(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn sha256(contents: &str) -> String {
+    format!("{:x}", Sha256::digest(contents.as_bytes()))
+}
+
 async fn command_works(cmd: &str) -> bool {
     Command::new(cmd)
         .arg("-h")
+        .stdout(output::child_stdout())
         .status()
         .await
         .map(|s| s.success())
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modrinth::Dependency;
+
+    #[test]
+    fn writes_modrinth_metafiles() {
+        let link = ModrinthProject {
+            id: "AANobbMI".to_string(),
+            name: "sodium-fabric-0.6.0.jar".to_string(),
+            download_link: "https://cdn.modrinth.com/data/AANobbMI/versions/abc/sodium.jar"
+                .to_string(),
+            sha512: "512".to_string(),
+            version_number: "0.6.0".to_string(),
+            size: 1,
+            version_id: "abc".to_string(),
+            sha1: "160".to_string(),
+            dependencies: Vec::new(),
+        };
+
+        assert_eq!(
+            metafile(&link, None).unwrap(),
+            "# Written by modrinth-collection-downloader\n\
+             name = \"sodium-fabric-0.6.0.jar\"\n\
+             filename = \"sodium-fabric-0.6.0.jar\"\n\
+             side = \"both\"\n\
+             \n\
+             [download]\n\
+             url = \"https://cdn.modrinth.com/data/AANobbMI/versions/abc/sodium.jar\"\n\
+             hash-format = \"sha1\"\n\
+             hash = \"160\"\n\
+             \n\
+             [update]\n\
+             [update.modrinth]\n\
+             mod-id = \"AANobbMI\"\n\
+             version = \"abc\"\n"
+        );
+    }
+
+    #[test]
+    fn reads_the_pack_version() {
+        let pack = "name = \"Pack\"\nversion = \"1.2.0\"\n\n[versions]\nversion = \"x\"\n";
+        assert_eq!(top_level_string(pack, "version"), Some("1.2.0".to_string()));
+        assert_eq!(
+            top_level_string("[index]\nversion = \"1\"\n", "version"),
+            None
+        );
+    }
+
+    #[test]
+    fn finds_missing_dependencies() {
+        let project = |id: &str, dependencies: &[(&str, &str)]| ModrinthProject {
+            id: id.to_string(),
+            name: format!("{id}.jar"),
+            download_link: String::new(),
+            sha512: String::new(),
+            version_number: String::new(),
+            size: 0,
+            version_id: String::new(),
+            sha1: String::new(),
+            dependencies: dependencies
+                .iter()
+                .map(|(id, kind)| Dependency {
+                    version_id: None,
+                    project_id: Some(id.to_string()),
+                    file_name: None,
+                    dependency_type: kind.to_string(),
+                })
+                .collect(),
+        };
+        let list = [
+            project("sodium", &[("fabric-api", "required")]),
+            project(
+                "iris",
+                &[("sodium", "required"), ("fabric-api", "required")],
+            ),
+            project("modmenu", &[("cloth", "optional")]),
+        ];
+
+        let missing = missing_dependencies(&list);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing["fabric-api"], ["sodium", "iris"]);
+    }
+
+    #[test]
+    fn updates_the_index_hash() {
+        let pack = "name = \"Pack\"\nhash = \"top\"\n\n[index]\nfile = \"index.toml\"\nhash = \"old\"\n\n[versions]\nminecraft = \"1.21.1\"\n";
+        assert_eq!(
+            set_index_hash(pack, "new").as_deref(),
+            Some(
+                "name = \"Pack\"\nhash = \"top\"\n\n[index]\nfile = \"index.toml\"\nhash = \"new\"\n\n[versions]\nminecraft = \"1.21.1\"\n"
+            )
+        );
+        assert_eq!(set_index_hash("name = \"Pack\"\n", "new"), None);
+    }
+
+    #[test]
+    fn escapes_toml_strings() {
+        assert_eq!(toml_string("plain"), "\"plain\"");
+        assert_eq!(
+            toml_string("a \"quoted\" C:\\path\n"),
+            "\"a \\\"quoted\\\" C:\\\\path\\n\""
+        );
+    }
+}
@@ -0,0 +1,264 @@
+// Dry runs, everything a run would do without writing to the mods or server directory.
+
+use crate::cache::Cache;
+use crate::download::{PlannedAction, file_name, plan_download};
+use crate::log::stale_files;
+use crate::modrinth::{ApiClient, ModrinthProject};
+use crate::mrpack::{Modpack, download_dir, override_files};
+use crate::output;
+use crate::packwiz::{DependencyPolicy, PackInfo, pack_metafiles};
+use anyhow::Result;
+use clap::ValueEnum;
+use colored::Colorize;
+use indicatif::HumanBytes;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// How a plan is printed.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PlanFormat {
+    Table,
+    Json,
+}
+
+/// What a run would do.
+#[derive(Serialize)]
+pub struct Plan {
+    pub loader: String,
+    pub game_version: String,
+    pub files: Vec<PlannedFile>,
+    /// Old versions of updated mods from an earlier run, deleted if you agree to it.
+    pub delete: Vec<PathBuf>,
+    /// Files from an earlier run whose project left the pack, they are only reported.
+    pub unused: Vec<PathBuf>,
+    /// Modpack override files, relative to the server directory.
+    pub overrides: Vec<PathBuf>,
+    /// Modpacks whose files are only known once their `.mrpack` is downloaded, which a dry run
+    /// doesn't do.
+    pub modpacks: Vec<String>,
+    /// Projects without a compatible version.
+    pub failed: Vec<String>,
+    /// Set when the server would be installed, describes what.
+    pub server: Option<String>,
+    /// packwiz files, when a pack would be built.
+    pub packwiz: Vec<String>,
+    /// packwiz commands run after building the pack.
+    pub packwiz_commands: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct PlannedFile {
+    #[serde(flatten)]
+    pub project: ModrinthProject,
+    pub path: PathBuf,
+    pub action: PlannedAction,
+}
+
+impl Plan {
+    /// Plans the download step, `dir` is the mods directory. Modpack files outside `mods/` and
+    /// their overrides go into its parent.
+    pub async fn new(
+        loader: &str,
+        game_version: &str,
+        links: &[ModrinthProject],
+        modpacks: &[Modpack],
+        dir: &Path,
+        cache: Option<&Cache>,
+    ) -> Result<Plan> {
+        let root = dir.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        let mut files = plan_files(links, dir, cache).await?;
+        let mut overrides = Vec::new();
+        let mut unread = Vec::new();
+        for pack in modpacks {
+            for (subdir, other) in &pack.other {
+                files.extend(plan_files(other, &root.join(subdir), cache).await?);
+            }
+            match &pack.mrpack {
+                Some(mrpack) => overrides.extend(override_files(mrpack).await?),
+                None => {
+                    files.extend(
+                        plan_files(std::slice::from_ref(&pack.file), &download_dir(), cache)
+                            .await?,
+                    );
+                    unread.push(pack.name.clone());
+                }
+            }
+        }
+
+        let stale = stale_files(dir, links).await?;
+        Ok(Plan {
+            loader: loader.to_string(),
+            game_version: game_version.to_string(),
+            files,
+            delete: stale
+                .iter()
+                .filter(|f| f.replaced)
+                .map(|f| f.path.clone())
+                .collect(),
+            unused: stale
+                .iter()
+                .filter(|f| !f.replaced)
+                .map(|f| f.path.clone())
+                .collect(),
+            overrides,
+            modpacks: unread,
+            failed: Vec::new(),
+            server: None,
+            packwiz: Vec::new(),
+            packwiz_commands: Vec::new(),
+        })
+    }
+
+    /// Adds the files a packwiz pack build would write.
+    pub async fn with_packwiz(
+        mut self,
+        api: &ApiClient,
+        dir: &Path,
+        info: &PackInfo,
+        links: &[ModrinthProject],
+        dependencies: DependencyPolicy,
+    ) -> Result<Plan> {
+        let pack_dir = dir.parent().unwrap_or(dir).join("packwiz");
+        let (files, _) = pack_metafiles(api, info, links, dependencies).await?;
+        self.packwiz = files
+            .into_iter()
+            .map(|file| file.path)
+            .chain(["index.toml".to_string(), "pack.toml".to_string()])
+            .map(|path| pack_dir.join(path).display().to_string())
+            .collect();
+        self.packwiz_commands = vec![format!("packwiz mr export (in {})", pack_dir.display())];
+        Ok(self)
+    }
+
+    /// Prints the plan, always as JSON in JSON mode.
+    pub fn print(&self, format: PlanFormat) -> Result<()> {
+        if output::is_json() {
+            output::event("plan", self);
+            return Ok(());
+        }
+
+        match format {
+            PlanFormat::Json => println!("{}", serde_json::to_string_pretty(self)?),
+            PlanFormat::Table => self.print_table(),
+        }
+        Ok(())
+    }
+
+    fn print_table(&self) {
+        say!(
+            "{}",
+            format!(
+                "Plan for {} on Minecraft {}, nothing has been written",
+                self.loader, self.game_version
+            )
+            .bright_green()
+        );
+
+        let width = self
+            .files
+            .iter()
+            .map(|f| f.path.display().to_string().len())
+            .max()
+            .unwrap_or(0)
+            .max("File".len());
+
+        say!(
+            "{:<width$}  {:<10}  {:>10}  Version",
+            "File",
+            "Action",
+            "Size"
+        );
+        for file in &self.files {
+            let action = match file.action {
+                PlannedAction::Download => "download".bright_green(),
+                PlannedAction::Skip => "up to date".normal(),
+                PlannedAction::Cache => "from cache".bright_green(),
+            };
+            say!(
+                "{:<width$}  {:<10}  {:>10}  {}",
+                file.path.display().to_string(),
+                action,
+                HumanBytes(file.project.size).to_string(),
+                file.project.version_number
+            );
+        }
+
+        for path in &self.delete {
+            say!(
+                "{:<width$}  {}",
+                path.display().to_string(),
+                "delete if asked".bright_red()
+            );
+        }
+        for path in &self.unused {
+            say!(
+                "{:<width$}  {}",
+                path.display().to_string(),
+                "no longer in the pack, kept"
+            );
+        }
+
+        let count = |action: PlannedAction| self.files.iter().filter(move |f| f.action == action);
+        say!(
+            "{}",
+            format!(
+                "Download: {} ({}), from cache: {}, up to date: {}, delete: {}, override files: {}",
+                count(PlannedAction::Download).count(),
+                HumanBytes(count(PlannedAction::Download).map(|f| f.project.size).sum()),
+                count(PlannedAction::Cache).count(),
+                count(PlannedAction::Skip).count(),
+                self.delete.len(),
+                self.overrides.len()
+            )
+            .bright_green()
+        );
+
+        if !self.modpacks.is_empty() {
+            say!(
+                "Not counted: the mods and overrides of {}, they are read from the downloaded modpack",
+                self.modpacks.join(", ")
+            );
+        }
+
+        if !self.failed.is_empty() {
+            say!(
+                "{}",
+                format!("No compatible version for: {}", self.failed.join(", ")).bright_red()
+            );
+        }
+
+        if let Some(server) = &self.server {
+            say!("If you set up the server: {server}");
+        }
+
+        if !self.packwiz.is_empty() {
+            say!("If you build a packwiz pack, it writes:");
+            for file in &self.packwiz {
+                say!("  {file}");
+            }
+            say!("and runs:");
+            for command in &self.packwiz_commands {
+                say!("  {command}");
+            }
+        }
+    }
+}
+
+async fn plan_files(
+    links: &[ModrinthProject],
+    dir: &Path,
+    cache: Option<&Cache>,
+) -> Result<Vec<PlannedFile>> {
+    let mut files = Vec::new();
+
+    for project in links {
+        files.push(PlannedFile {
+            project: project.clone(),
+            path: dir.join(file_name(project)?),
+            action: plan_download(project, dir, cache).await?,
+        });
+    }
+
+    Ok(files)
+}
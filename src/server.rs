@@ -0,0 +1,381 @@
+// Dedicated server setup, turns the directory next to the executable into a runnable server.
+
+use crate::output;
+use anyhow::{Context, Result, anyhow};
+use reqwest::Client;
+use reqwest::header::USER_AGENT;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::fs;
+use tokio::process::Command;
+
+const FABRIC_META_URL: &str = "https://meta.fabricmc.net/v2/versions";
+const QUILT_META_URL: &str = "https://meta.quiltmc.org/v3/versions";
+const QUILT_INSTALLER_URL: &str =
+    "https://quiltmc.org/api/v1/download-latest-installer/java-universal";
+const NEOFORGE_VERSIONS_URL: &str =
+    "https://maven.neoforged.net/api/maven/versions/releases/net/neoforged/neoforge";
+const NEOFORGE_MAVEN_URL: &str = "https://maven.neoforged.net/releases/net/neoforged/neoforge";
+const FORGE_PROMOTIONS_URL: &str =
+    "https://files.minecraftforge.net/net/minecraftforge/forge/promotions_slim.json";
+const FORGE_MAVEN_URL: &str = "https://maven.minecraftforge.net/net/minecraftforge/forge";
+
+const INSTALLER_NAME: &str = "installer.jar";
+
+/// How the installed server gets started.
+enum Launch {
+    /// A single server jar, started with `java -jar`.
+    Jar(String),
+    /// The installer generated its own `run.sh` / `run.bat`.
+    RunScript,
+}
+
+/// Installs the server for the chosen platform into the parent of the mods directory, writes the
+/// eula if the user accepted it, and creates `start.sh` / `start.bat`.
+pub async fn setup_server(
+    mut dir: PathBuf,
+    loader: &str,
+    minecraft_version: &str,
+    accept_eula: bool,
+) -> Result<()> {
+    dir.pop();
+
+    let launch = match loader {
+        "fabric" => install_fabric(&dir, minecraft_version).await?,
+        "quilt" => install_quilt(&dir, minecraft_version).await?,
+        "neoforge" => install_neoforge(&dir, minecraft_version).await?,
+        "forge" => install_forge(&dir, minecraft_version).await?,
+        _ => return Err(anyhow!("Unsupported server platform: {loader}")),
+    };
+
+    if accept_eula {
+        fs::write(dir.join("eula.txt"), "eula=true\n").await?;
+    }
+
+    write_start_scripts(&dir, &launch).await?;
+
+    Ok(())
+}
+
+/// Fabric provides a launcher jar that fetches everything else on first start.
+async fn install_fabric(dir: &Path, minecraft_version: &str) -> Result<Launch> {
+    let client = Client::new();
+
+    let loader = fabric_loader_version(&client, minecraft_version).await?;
+
+    let installers: Vec<FabricInstaller> =
+        get_json(&client, &format!("{FABRIC_META_URL}/installer")).await?;
+    let installer = installers
+        .iter()
+        .find(|i| i.stable)
+        .or_else(|| installers.first())
+        .ok_or_else(|| anyhow!("No Fabric installer versions available"))?;
+
+    let url = format!(
+        "{FABRIC_META_URL}/loader/{}/{}/{}/server/jar",
+        minecraft_version, loader, installer.version
+    );
+    let jar_name = format!(
+        "fabric-server-mc.{}-loader.{}-launcher.{}.jar",
+        minecraft_version, loader, installer.version
+    );
+
+    download_to(&client, &url, &dir.join(&jar_name)).await?;
+
+    Ok(Launch::Jar(jar_name))
+}
+
+/// Quilt has no direct server jar, so the installer is run in server mode.
+async fn install_quilt(dir: &Path, minecraft_version: &str) -> Result<Launch> {
+    let client = Client::new();
+    let installer = dir.join(INSTALLER_NAME);
+    download_to(&client, QUILT_INSTALLER_URL, &installer).await?;
+
+    run_installer(
+        dir,
+        &[
+            "install".to_string(),
+            "server".to_string(),
+            minecraft_version.to_string(),
+            "--download-server".to_string(),
+            format!("--install-dir={}", dir.display()),
+        ],
+    )
+    .await?;
+
+    Ok(Launch::Jar("quilt-server-launch.jar".to_string()))
+}
+
+/// NeoForge ships an installer per version.
+async fn install_neoforge(dir: &Path, minecraft_version: &str) -> Result<Launch> {
+    let client = Client::new();
+
+    let version = neoforge_version(&client, minecraft_version).await?;
+
+    let url = format!("{NEOFORGE_MAVEN_URL}/{version}/neoforge-{version}-installer.jar");
+    download_to(&client, &url, &dir.join(INSTALLER_NAME)).await?;
+
+    run_installer(
+        dir,
+        &["--install-server".to_string(), dir.display().to_string()],
+    )
+    .await?;
+
+    detect_launch(dir, "neoforge-").await
+}
+
+/// Forge ships an installer per version, like NeoForge.
+async fn install_forge(dir: &Path, minecraft_version: &str) -> Result<Launch> {
+    let client = Client::new();
+
+    let version = forge_version(&client, minecraft_version).await?;
+
+    let full = format!("{minecraft_version}-{version}");
+    let url = format!("{FORGE_MAVEN_URL}/{full}/forge-{full}-installer.jar");
+    download_to(&client, &url, &dir.join(INSTALLER_NAME)).await?;
+
+    run_installer(
+        dir,
+        &["--installServer".to_string(), dir.display().to_string()],
+    )
+    .await?;
+
+    detect_launch(dir, "forge-").await
+}
+
+/// The loader version a pack for `minecraft_version` should use, from the loader's own metadata.
+pub async fn loader_version(loader: &str, minecraft_version: &str) -> Result<String> {
+    let client = Client::new();
+
+    match loader {
+        "fabric" => fabric_loader_version(&client, minecraft_version).await,
+        "quilt" => quilt_loader_version(&client, minecraft_version).await,
+        "neoforge" => neoforge_version(&client, minecraft_version).await,
+        "forge" => forge_version(&client, minecraft_version).await,
+        _ => Err(anyhow!("Unsupported loader: {loader}")),
+    }
+}
+
+/// Newest Fabric loader that supports the Minecraft version.
+async fn fabric_loader_version(client: &Client, minecraft_version: &str) -> Result<String> {
+    let loaders: Vec<LoaderEntry> = get_json(
+        client,
+        &format!("{FABRIC_META_URL}/loader/{minecraft_version}"),
+    )
+    .await?;
+    loaders
+        .into_iter()
+        .next()
+        .map(|l| l.loader.version)
+        .ok_or_else(|| anyhow!("Fabric has no loader for Minecraft {minecraft_version}"))
+}
+
+/// Quilt meta has the same layout as Fabric's.
+async fn quilt_loader_version(client: &Client, minecraft_version: &str) -> Result<String> {
+    let loaders: Vec<LoaderEntry> = get_json(
+        client,
+        &format!("{QUILT_META_URL}/loader/{minecraft_version}"),
+    )
+    .await?;
+    loaders
+        .into_iter()
+        .next()
+        .map(|l| l.loader.version)
+        .ok_or_else(|| anyhow!("Quilt has no loader for Minecraft {minecraft_version}"))
+}
+
+/// NeoForge versions mirror the Minecraft version, 1.21.1 maps to 21.1.x and 1.21 to 21.0.x.
+async fn neoforge_version(client: &Client, minecraft_version: &str) -> Result<String> {
+    let mut parts = minecraft_version.split('.').skip(1);
+    let major = parts
+        .next()
+        .ok_or_else(|| anyhow!("NeoForge does not support Minecraft {minecraft_version}"))?;
+    let minor = parts.next().unwrap_or("0");
+    let prefix = format!("{major}.{minor}.");
+
+    let versions: NeoForgeVersions = get_json(client, NEOFORGE_VERSIONS_URL).await?;
+    let matching: Vec<&String> = versions
+        .versions
+        .iter()
+        .filter(|v| v.starts_with(&prefix))
+        .collect();
+    matching
+        .iter()
+        .rev()
+        .find(|v| !v.contains("beta"))
+        .or_else(|| matching.last())
+        .map(|v| v.to_string())
+        .ok_or_else(|| anyhow!("NeoForge has no release for Minecraft {minecraft_version}"))
+}
+
+/// Forge publishes recommended and latest builds per Minecraft version.
+async fn forge_version(client: &Client, minecraft_version: &str) -> Result<String> {
+    let promotions: ForgePromotions = get_json(client, FORGE_PROMOTIONS_URL).await?;
+    promotions
+        .promos
+        .get(&format!("{minecraft_version}-recommended"))
+        .or_else(|| {
+            promotions
+                .promos
+                .get(&format!("{minecraft_version}-latest"))
+        })
+        .cloned()
+        .ok_or_else(|| anyhow!("Forge has no build for Minecraft {minecraft_version}"))
+}
+
+/// Runs the downloaded installer with java, then cleans it up.
+async fn run_installer(dir: &Path, args: &[String]) -> Result<()> {
+    let java_works = Command::new("java")
+        .arg("-version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map(|s| s.success())
+        .unwrap_or(false);
+    anyhow::ensure!(java_works, "Java is required to run the server installer");
+
+    let installer = dir.join(INSTALLER_NAME);
+    let status = Command::new("java")
+        .arg("-jar")
+        .arg(&installer)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::inherit())
+        .stdout(output::child_stdout())
+        .stderr(Stdio::inherit())
+        .status()
+        .await
+        .context("running server installer")?;
+    anyhow::ensure!(status.success(), "server installer failed");
+
+    let _ = fs::remove_file(&installer).await;
+    let _ = fs::remove_file(dir.join(format!("{INSTALLER_NAME}.log"))).await;
+
+    Ok(())
+}
+
+/// Modern installers write their own run scripts, older Forge versions leave a server jar.
+async fn detect_launch(dir: &Path, jar_prefix: &str) -> Result<Launch> {
+    if fs::try_exists(dir.join("run.sh")).await? || fs::try_exists(dir.join("run.bat")).await? {
+        return Ok(Launch::RunScript);
+    }
+
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(jar_prefix) && name.ends_with(".jar") && !name.contains("installer") {
+            return Ok(Launch::Jar(name));
+        }
+    }
+
+    Err(anyhow!("Could not find the installed server jar"))
+}
+
+async fn write_start_scripts(dir: &Path, launch: &Launch) -> Result<()> {
+    let (sh, bat) = match launch {
+        Launch::Jar(jar) => (
+            format!("java -Xmx4G -jar {jar} nogui \"$@\""),
+            format!("java -Xmx4G -jar {jar} nogui %*"),
+        ),
+        Launch::RunScript => (
+            "sh ./run.sh nogui \"$@\"".to_string(),
+            "call run.bat nogui %*".to_string(),
+        ),
+    };
+
+    let sh_path = dir.join("start.sh");
+    fs::write(
+        &sh_path,
+        format!("#!/usr/bin/env sh\ncd \"$(dirname \"$0\")\"\n{sh}\n"),
+    )
+    .await?;
+    fs::write(
+        dir.join("start.bat"),
+        format!("@echo off\r\ncd /d \"%~dp0\"\r\n{bat}\r\npause\r\n"),
+    )
+    .await?;
+
+    // chmod on unix
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = fs::metadata(&sh_path).await?.permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&sh_path, perms).await?;
+    }
+
+    Ok(())
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(client: &Client, url: &str) -> Result<T> {
+    let resp = client
+        .get(url)
+        .header(
+            USER_AGENT,
+            "kay-xr/modrinth_collection_downloader/0.1.0 (archangel@angelware.net)",
+        )
+        .send()
+        .await
+        .with_context(|| format!("GET {url}"))?;
+
+    if resp.status().is_success() {
+        Ok(resp.json().await?)
+    } else {
+        Err(anyhow!(format!(
+            "GET {url} failed with status: {}",
+            resp.status()
+        )))
+    }
+}
+
+async fn download_to(client: &Client, url: &str, path: &Path) -> Result<()> {
+    let bytes = client
+        .get(url)
+        .header(
+            USER_AGENT,
+            "kay-xr/modrinth_collection_downloader/0.1.0 (archangel@angelware.net)",
+        )
+        .send()
+        .await
+        .with_context(|| format!("GET {url}"))?
+        .error_for_status()?
+        .bytes()
+        .await
+        .context("reading server download")?;
+
+    fs::write(path, &bytes).await?;
+
+    Ok(())
+}
+
+/// Fabric and Quilt meta loader listing, newest first.
+#[derive(Deserialize)]
+struct LoaderEntry {
+    loader: Loader,
+}
+
+#[derive(Deserialize)]
+struct Loader {
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct FabricInstaller {
+    version: String,
+    stable: bool,
+}
+
+/// NeoForge maven versions, oldest first.
+#[derive(Deserialize)]
+struct NeoForgeVersions {
+    versions: Vec<String>,
+}
+
+/// Forge promotions, keyed by `<minecraft>-recommended` and `<minecraft>-latest`.
+#[derive(Deserialize)]
+struct ForgePromotions {
+    promos: HashMap<String, String>,
+}
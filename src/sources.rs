@@ -0,0 +1,427 @@
+// Everything the user can point us at: collections, projects and versions, merged into one set.
+
+use crate::modrinth::{
+    ApiClient, ModrinthProject, find_collection, find_project, get_collection_details,
+    get_followed_projects, get_organization_projects, get_project, get_projects, get_user_projects,
+    get_version, missing_token_message,
+};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Project types that share the `/<type>/<slug>` URL layout on modrinth.com.
+const PROJECT_TYPES: &[&str] = &[
+    "mod",
+    "plugin",
+    "datapack",
+    "resourcepack",
+    "shader",
+    "modpack",
+    "project",
+];
+
+/// A single input the user gave us.
+#[derive(Debug, PartialEq)]
+pub enum Source {
+    Collection(String),
+    Project(String),
+    /// A specific version, by ID or by project and version number.
+    Version {
+        project: Option<String>,
+        version: String,
+    },
+    /// Every project a user published.
+    User(String),
+    Organization(String),
+    /// Every project a user follows.
+    Follows(String),
+    /// A bare ID or slug, which could be either a collection or a project.
+    Ambiguous(String),
+}
+
+/// Project ids of all sources, without duplicates, plus where each one came from, as labels like
+/// `collection:<id>` or `project:<slug>`. Projects given as a specific version are resolved
+/// already and live in `pinned` instead of `project_ids`. Modpack projects are kept apart in
+/// `modpacks`, since their files are installed instead of the project itself.
+pub struct ResolvedSources {
+    pub project_ids: Vec<String>,
+    pub pinned: Vec<ModrinthProject>,
+    pub modpacks: Vec<String>,
+    pub provenance: BTreeMap<String, Vec<String>>,
+    /// Every collection that was part of the sources.
+    pub collections: Vec<CollectionRef>,
+}
+
+/// A collection by id and name.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CollectionRef {
+    pub id: String,
+    pub name: String,
+    /// User id of the owner.
+    #[serde(default)]
+    pub user: String,
+}
+
+impl ResolvedSources {
+    /// Every project id, pinned ones included.
+    pub fn all_ids(&self) -> Vec<String> {
+        self.project_ids
+            .iter()
+            .cloned()
+            .chain(self.pinned.iter().map(|p| p.id.clone()))
+            .chain(self.modpacks.iter().cloned())
+            .collect()
+    }
+}
+
+/// Splits a comma or whitespace separated list of sources, see [`parse_source`].
+pub fn parse_sources(input: &str) -> Result<Vec<Source>> {
+    let sources = input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(parse_source)
+        .collect::<Result<Vec<Source>>>()?;
+
+    if sources.is_empty() {
+        Err(anyhow!("No collections or projects given"))
+    } else {
+        Ok(sources)
+    }
+}
+
+/// Parses one source. Accepts modrinth.com URLs (with or without scheme, `www.`, query string or
+/// trailing slash), explicit `collection:`, `project:`, `version:`, `user:`, `organization:` and
+/// `follows:` prefixes, and bare IDs or slugs.
+pub fn parse_source(input: &str) -> Result<Source> {
+    let input = input.trim();
+
+    let rest = input
+        .strip_prefix("https://")
+        .or_else(|| input.strip_prefix("http://"))
+        .unwrap_or(input);
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    let rest = rest.strip_prefix("www.").unwrap_or(rest);
+
+    if let Some(path) = rest.strip_prefix("modrinth.com")
+        && (path.is_empty() || path.starts_with('/'))
+    {
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        let source = match segments.as_slice() {
+            ["collection", id, ..] => Source::Collection(id.to_string()),
+            ["user", name, ..] => Source::User(name.to_string()),
+            ["organization", slug, ..] => Source::Organization(slug.to_string()),
+            ["version", version, ..] => Source::Version {
+                project: None,
+                version: version.to_string(),
+            },
+            [kind, slug, "version", version, ..] if PROJECT_TYPES.contains(kind) => {
+                Source::Version {
+                    project: Some(slug.to_string()),
+                    version: version.to_string(),
+                }
+            }
+            [kind, slug, ..] if PROJECT_TYPES.contains(kind) => Source::Project(slug.to_string()),
+            _ => return Err(anyhow!("Unsupported Modrinth URL: {input}")),
+        };
+
+        // Everything taken from the path ends up in an API URL
+        match &source {
+            Source::Version { project, version } => {
+                if let Some(project) = project {
+                    check_slug(project, input)?;
+                }
+                check_slug(version, input)?;
+            }
+            Source::Collection(value)
+            | Source::Project(value)
+            | Source::User(value)
+            | Source::Organization(value)
+            | Source::Follows(value)
+            | Source::Ambiguous(value) => check_slug(value, input)?,
+        }
+        return Ok(source);
+    }
+
+    if rest.contains('/') {
+        return Err(anyhow!("Not a Modrinth URL: {input}"));
+    }
+
+    if let Some((kind, value)) = rest.split_once(':') {
+        check_slug(value, input)?;
+        return match kind {
+            "collection" => Ok(Source::Collection(value.to_string())),
+            "project" => Ok(Source::Project(value.to_string())),
+            "version" => Ok(Source::Version {
+                project: None,
+                version: value.to_string(),
+            }),
+            "user" => Ok(Source::User(value.to_string())),
+            "organization" | "org" => Ok(Source::Organization(value.to_string())),
+            "follows" => Ok(Source::Follows(value.to_string())),
+            _ => Err(anyhow!("Unknown source type '{kind}' in {input}")),
+        };
+    }
+
+    check_slug(rest, input)?;
+    Ok(Source::Ambiguous(rest.to_string()))
+}
+
+/// IDs and slugs only contain letters, digits and a few separators, and are more than dots.
+fn check_slug(value: &str, input: &str) -> Result<()> {
+    if !value.chars().all(|c| c == '.')
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '+'))
+    {
+        Ok(())
+    } else {
+        Err(anyhow!("Invalid collection or project: {input}"))
+    }
+}
+
+/// Turns a collection URL or ID into the ID.
+pub fn parse_collection_id(input: &str) -> Result<String> {
+    match parse_source(input)? {
+        Source::Collection(id) | Source::Ambiguous(id) => Ok(id),
+        _ => Err(anyhow!("{input} is not a collection")),
+    }
+}
+
+/// Fetches every source and merges their projects. Loose projects are looked up so slugs turn
+/// into the same ids collections use, which keeps the dedupe working. Bare IDs are tried as a
+/// collection first and as a project second.
+pub async fn resolve_sources(api: &ApiClient, sources: &[Source]) -> Result<ResolvedSources> {
+    let mut project_ids = Vec::new();
+    let mut pinned: Vec<ModrinthProject> = Vec::new();
+    let mut provenance: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut collections: Vec<CollectionRef> = Vec::new();
+
+    for source in sources {
+        let (label, ids) = match source {
+            Source::Collection(id) => {
+                let collection = get_collection_details(api, id).await?;
+                collections.push(CollectionRef {
+                    id: collection.id.clone(),
+                    name: collection.name,
+                    user: collection.user,
+                });
+                (format!("collection:{}", collection.id), collection.projects)
+            }
+            Source::Project(slug) => (
+                format!("project:{slug}"),
+                vec![get_project(api, slug).await?.id],
+            ),
+            Source::Version { project, version } => {
+                let ver = get_version(api, project.as_deref(), version).await?;
+                let proj = ModrinthProject::from_version(&ver.project_id, &ver)
+                    .ok_or_else(|| anyhow!("Version {version} has no files"))?;
+                pinned.retain(|p| p.id != proj.id);
+                pinned.push(proj);
+                (format!("version:{}", ver.id), vec![ver.project_id])
+            }
+            Source::User(name) => (format!("user:{name}"), get_user_projects(api, name).await?),
+            Source::Organization(slug) => (
+                format!("organization:{slug}"),
+                get_organization_projects(api, slug).await?,
+            ),
+            Source::Follows(name) => (
+                format!("follows:{name}"),
+                get_followed_projects(api, name).await?,
+            ),
+            // Only "not found" means try the next kind, other errors (rate limits, the network)
+            // are passed on as they are
+            Source::Ambiguous(id) => match find_collection(api, id).await? {
+                Some(collection) => {
+                    collections.push(CollectionRef {
+                        id: collection.id.clone(),
+                        name: collection.name,
+                        user: collection.user,
+                    });
+                    (format!("collection:{}", collection.id), collection.projects)
+                }
+                None => match find_project(api, id).await? {
+                    Some(project) => (format!("project:{id}"), vec![project.id]),
+                    None => {
+                        let problem = format!("{id} is neither a collection nor a project");
+                        return Err(if api.has_token() {
+                            anyhow!(problem)
+                        } else {
+                            anyhow!(missing_token_message(&problem))
+                        });
+                    }
+                },
+            },
+        };
+
+        for id in ids {
+            let entry = provenance.entry(id.clone()).or_default();
+            if entry.is_empty() {
+                project_ids.push(id);
+            }
+            entry.push(label.clone());
+        }
+    }
+
+    // A pinned version wins over the latest version a collection would give us
+    project_ids.retain(|id| !pinned.iter().any(|p| &p.id == id));
+
+    let modpacks: Vec<String> = get_projects(api, &project_ids)
+        .await?
+        .into_iter()
+        .filter(|p| p.project_type == "modpack")
+        .map(|p| p.id)
+        .collect();
+    project_ids.retain(|id| !modpacks.contains(id));
+
+    Ok(ResolvedSources {
+        project_ids,
+        pinned,
+        modpacks,
+        provenance,
+        collections,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Source {
+        parse_source(input).unwrap()
+    }
+
+    #[test]
+    fn collection_urls() {
+        let expected = Source::Collection("AbCd1234".to_string());
+        assert_eq!(parse("https://modrinth.com/collection/AbCd1234"), expected);
+        assert_eq!(parse("http://modrinth.com/collection/AbCd1234"), expected);
+        assert_eq!(
+            parse("https://www.modrinth.com/collection/AbCd1234"),
+            expected
+        );
+        assert_eq!(parse("modrinth.com/collection/AbCd1234"), expected);
+        assert_eq!(parse("https://modrinth.com/collection/AbCd1234/"), expected);
+        assert_eq!(
+            parse("https://modrinth.com/collection/AbCd1234?foo=bar"),
+            expected
+        );
+        assert_eq!(
+            parse("https://modrinth.com/collection/AbCd1234#top"),
+            expected
+        );
+        assert_eq!(
+            parse("  https://modrinth.com/collection/AbCd1234  "),
+            expected
+        );
+    }
+
+    #[test]
+    fn project_urls() {
+        for kind in PROJECT_TYPES {
+            assert_eq!(
+                parse(&format!("https://modrinth.com/{kind}/sodium")),
+                Source::Project("sodium".to_string())
+            );
+        }
+        assert_eq!(
+            parse("https://modrinth.com/mod/fabric-api/versions?g=1.21.1"),
+            Source::Project("fabric-api".to_string())
+        );
+    }
+
+    #[test]
+    fn version_urls() {
+        assert_eq!(
+            parse("https://modrinth.com/mod/sodium/version/mc1.21.1-0.6.0-fabric"),
+            Source::Version {
+                project: Some("sodium".to_string()),
+                version: "mc1.21.1-0.6.0-fabric".to_string(),
+            }
+        );
+        assert_eq!(
+            parse("https://modrinth.com/version/IZskON6d"),
+            Source::Version {
+                project: None,
+                version: "IZskON6d".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn user_urls() {
+        assert_eq!(
+            parse("https://modrinth.com/user/jellysquid3"),
+            Source::User("jellysquid3".to_string())
+        );
+        assert_eq!(
+            parse("https://modrinth.com/organization/caffeinemc/"),
+            Source::Organization("caffeinemc".to_string())
+        );
+    }
+
+    #[test]
+    fn prefixes_and_bare_ids() {
+        assert_eq!(
+            parse("collection:AbCd1234"),
+            Source::Collection("AbCd1234".to_string())
+        );
+        assert_eq!(
+            parse("project:sodium"),
+            Source::Project("sodium".to_string())
+        );
+        assert_eq!(parse("user:someone"), Source::User("someone".to_string()));
+        assert_eq!(
+            parse("organization:caffeinemc"),
+            Source::Organization("caffeinemc".to_string())
+        );
+        assert_eq!(
+            parse("org:caffeinemc"),
+            Source::Organization("caffeinemc".to_string())
+        );
+        assert_eq!(
+            parse("follows:someone"),
+            Source::Follows("someone".to_string())
+        );
+        assert_eq!(parse("AbCd1234"), Source::Ambiguous("AbCd1234".to_string()));
+        assert_eq!(
+            parse("fabric-api"),
+            Source::Ambiguous("fabric-api".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_inputs() {
+        assert!(parse_source("").is_err());
+        assert!(parse_source("https://modrinth.com/collection/").is_err());
+        assert!(parse_source("https://modrinth.com/").is_err());
+        assert!(parse_source("https://modrinth.com/discover/mods").is_err());
+        assert!(parse_source("https://example.com/collection/AbCd1234").is_err());
+        assert!(parse_source("modrinth.community/collection/AbCd1234").is_err());
+        assert!(parse_source("team:someone").is_err());
+        assert!(parse_source("not a slug!").is_err());
+        assert!(parse_source("https://modrinth.com/mod/..").is_err());
+        assert!(parse_source("modrinth.com/mod/x\"y").is_err());
+        assert!(parse_source("modrinth.com/collection/%2e%2e").is_err());
+        assert!(parse_source("modrinth.com/mod/sodium/version/..").is_err());
+        assert!(parse_source("project:..").is_err());
+        assert!(parse_source("..").is_err());
+    }
+
+    #[test]
+    fn multiple_sources() {
+        let sources = parse_sources(
+            "https://modrinth.com/collection/AAAA, https://modrinth.com/collection/BBBB\nsodium",
+        )
+        .unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                Source::Collection("AAAA".to_string()),
+                Source::Collection("BBBB".to_string()),
+                Source::Ambiguous("sodium".to_string()),
+            ]
+        );
+        assert!(parse_sources(" , ").is_err());
+    }
+}
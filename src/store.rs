@@ -0,0 +1,102 @@
+// On-disk store of Modrinth API responses. Entries are reused while fresh, revalidated with
+// their ETag / Last-Modified once stale, and replayed as-is in offline mode.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+
+pub struct ResponseStore {
+    root: PathBuf,
+}
+
+/// A stored response body together with its key, the URL it came from, and its validators.
+#[derive(Deserialize, Serialize)]
+pub struct StoredResponse {
+    pub url: String,
+    pub body: String,
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    /// Unix timestamp of the last time the response was fetched or revalidated.
+    #[serde(default)]
+    pub fetched_at: u64,
+}
+
+impl StoredResponse {
+    pub fn new(
+        url: &str,
+        body: String,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    ) -> Self {
+        Self {
+            url: url.to_string(),
+            body,
+            etag,
+            last_modified,
+            fetched_at: now(),
+        }
+    }
+
+    /// Whether the response was fetched less than `ttl_secs` ago.
+    pub fn is_fresh(&self, ttl_secs: u64) -> bool {
+        now().saturating_sub(self.fetched_at) < ttl_secs
+    }
+
+    /// Marks the response as just revalidated.
+    pub fn touch(&mut self) {
+        self.fetched_at = now();
+    }
+}
+
+impl ResponseStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Entries are named after the sha256 of their URL.
+    fn entry_path(&self, url: &str) -> PathBuf {
+        let key = format!("{:x}", Sha256::digest(url.as_bytes()));
+        self.root.join(format!("{key}.json"))
+    }
+
+    pub async fn load(&self, url: &str) -> Result<Option<StoredResponse>> {
+        let path = self.entry_path(url);
+        if !fs::try_exists(&path).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_str(
+            &fs::read_to_string(&path).await?,
+        )?))
+    }
+
+    /// Drops the stored response for `url`, so the next request goes to the network.
+    pub async fn remove(&self, url: &str) -> Result<()> {
+        let path = self.entry_path(url);
+        if fs::try_exists(&path).await? {
+            fs::remove_file(&path).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn save(&self, stored: &StoredResponse) -> Result<()> {
+        fs::create_dir_all(&self.root).await?;
+        fs::write(self.entry_path(&stored.url), serde_json::to_string(stored)?).await?;
+
+        Ok(())
+    }
+}
+
+/// Seconds since the unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
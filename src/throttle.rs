@@ -0,0 +1,49 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Token bucket shared by all download tasks, limiting the combined throughput to `rate` bytes
+/// per second. Up to one second of traffic can burst through before tasks start waiting.
+pub struct Throttle {
+    rate: f64,
+    state: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            rate: bytes_per_sec as f64,
+            state: Mutex::new(Bucket {
+                tokens: bytes_per_sec as f64,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes `bytes` out of the bucket, sleeping until the bucket has caught up if it runs dry.
+    /// Chunks larger than the bucket are allowed and simply put it into debt.
+    pub async fn consume(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = self.state.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.last).as_secs_f64() * self.rate;
+            bucket.tokens = (bucket.tokens + refill).min(self.rate);
+            bucket.last = now;
+            bucket.tokens -= bytes as f64;
+
+            if bucket.tokens < 0.0 {
+                Some(Duration::from_secs_f64(-bucket.tokens / self.rate))
+            } else {
+                None
+            }
+        };
+
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}